@@ -1,75 +0,0 @@
-#~/bin/sh
-
-./mic_db_fill -s /data1/2idd/2025-1 -n 3 -r 2025-1 -b 2-ID-D > 2idd.log
-./mic_db_fill -s /data1/2idd/2023-3 -n 3 -r 2023-3 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2022-3 -n 3 -r 2022-3 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2022-2 -n 3 -r 2022-2 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2022-1 -n 3 -r 2022-1 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2021-3 -n 3 -r 2021-3 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2021-2 -n 3 -r 2021-2 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2021-1 -n 3 -r 2021-1 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2020-3 -n 3 -r 2020-3 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2020-1 -n 3 -r 2020-1 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2019-2 -n 3 -r 2019-2 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2019-1 -n 3 -r 2019-1 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2018-3 -n 3 -r 2018-3 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2018-2 -n 3 -r 2018-2 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2018-1 -n 3 -r 2018-1 -b 2-ID-D >> 2idd.log
-./mic_db_fill -s /data1/2idd/2017-3 -n 3 -r 2017-3 -b 2-ID-D >> 2idd.log
-
-./mic_db_fill -s /data1/2ide/2025-1 -n 3 -r 2025-1 -b 2-ID-E > 2ide.log
-./mic_db_fill -s /data1/2ide/2024-3 -n 3 -r 2024-3 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2024-1 -n 3 -r 2024-1 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2023-1 -n 3 -r 2023-1 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2022-3 -n 3 -r 2022-3 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2022-2 -n 3 -r 2022-2 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2022-1 -n 3 -r 2022-1 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2021-3 -n 3 -r 2021-3 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2021-2 -n 3 -r 2021-2 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2021-1 -n 3 -r 2021-1 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2020-3 -n 3 -r 2020-3 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2019-2 -n 3 -r 2019-2 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2019-1 -n 3 -r 2019-1 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2018-3 -n 3 -r 2018-3 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2018-2 -n 3 -r 2018-2 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2018-1 -n 3 -r 2018-1 -b 2-ID-E >> 2ide.log
-./mic_db_fill -s /data1/2ide/2017-3 -n 3 -r 2017-3 -b 2-ID-E >> 2ide.log
-
-./mic_db_fill -s /data1/8bm/2023-1 -n 3 -r 2023-1 -b 8-BM-B > 8bm.log
-./mic_db_fill -s /data1/8bm/2022-3 -n 3 -r 2022-3 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2022-2 -n 3 -r 2022-2 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2022-1 -n 3 -r 2022-1 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2021-3 -n 3 -r 2021-3 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2021-2 -n 3 -r 2021-2 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2021-1 -n 3 -r 2021-1 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2020-3 -n 3 -r 2020-3 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2020-2 -n 3 -r 2020-2 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2020-1 -n 3 -r 2020-1 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2019-3 -n 3 -r 2019-3 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2019-2 -n 3 -r 2019-2 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2019-1 -n 3 -r 2019-1 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2018-2 -n 3 -r 2018-2 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2018-1 -n 3 -r 2018-1 -b 8-BM-B >> 8bm.log
-./mic_db_fill -s /data1/8bm/2014-3 -n 3 -r 2014-3 -b 8-BM-B >> 8bm.log
-
-#./mic_db_fill -s /data1/bnp/2023-1 -n 3 -r 2023-1 -b 9-ID-B,C > bnp.log
-#./mic_db_fill -s /data1/bnp/2022-3 -n 3 -r 2022-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2022-2 -n 3 -r 2022-2 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2022-1 -n 3 -r 2022-1 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2021-3 -n 3 -r 2021-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2021-2 -n 3 -r 2021-2 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2021-1 -n 3 -r 2021-1 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2020-3 -n 3 -r 2020-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2020-2 -n 3 -r 2020-2 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2020-1 -n 3 -r 2020-1 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2019-3 -n 3 -r 2019-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2019-2 -n 3 -r 2019-2 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2019-1 -n 3 -r 2019-1 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2018-3 -n 3 -r 2018-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2018-2 -n 3 -r 2018-2 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2018-1 -n 3 -r 2018-1 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2017-3 -n 3 -r 2017-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2017-2 -n 3 -r 2017-2 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2017-1 -n 3 -r 2017-1 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2016-3 -n 3 -r 2016-3 -b 9-ID-B,C >> bnp.log
-./mic_db_fill -s /data1/bnp/2016-2 -n 3 -r 2016-2 -b 9-ID-B,C >> bnp.log
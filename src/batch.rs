@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+
+// Manifest for the batch subcommand, one entry per beamline.
+// Templates can use {beamline}, {dir} and {run}, ex: "/data1/{dir}/{run}"
+
+#[derive(Deserialize, Debug)]
+pub struct Manifest
+{
+    pub path_template: String,
+    #[serde(default = "default_num_recursive")]
+    pub num_recursive: u32,
+    // load the schedule of each run from a file instead of the scheduling API
+    pub schedule_template: Option<String>,
+    pub beamlines: Vec<BeamlineEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BeamlineEntry
+{
+    pub name: String,
+    // directory name used in the templates, ex: 2idd for 2-ID-D
+    pub dir: Option<String>,
+    pub runs: Vec<String>,
+    // per beamline overrides of the manifest defaults
+    pub path_template: Option<String>,
+    pub num_recursive: Option<u32>,
+    pub schedule_template: Option<String>,
+}
+
+fn default_num_recursive() -> u32
+{
+    2
+}
+
+#[derive(Debug)]
+pub enum ManifestError
+{
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+    UnknownFormat(String),
+}
+
+// One ingestion the manifest expands to
+#[derive(Debug, Clone)]
+pub struct BatchJob
+{
+    pub beamline: String,
+    pub run: String,
+    pub search_dir: String,
+    pub num_recursive: u32,
+    pub schedule_file: Option<String>,
+}
+
+fn fill_template(template: &str, entry: &BeamlineEntry, run: &str) -> String
+{
+    let dir = entry.dir.as_deref().unwrap_or(&entry.name);
+    template.replace("{beamline}", &entry.name).replace("{dir}", dir).replace("{run}", run)
+}
+
+impl Manifest
+{
+    // Format is picked by extension: .toml, .yaml or .yml
+    pub fn load(file_path: &str) -> Result<Self, ManifestError>
+    {
+        let contents = fs::read_to_string(file_path).map_err(ManifestError::Io)?;
+        let ext = Path::new(file_path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match ext.as_str()
+        {
+            "toml" => toml::from_str(&contents).map_err(ManifestError::Toml),
+            "yaml" | "yml" => serde_yaml::from_str(&contents).map_err(ManifestError::Yaml),
+            _ => Err(ManifestError::UnknownFormat(ext)),
+        }
+    }
+
+    pub fn jobs(&self) -> Vec<BatchJob>
+    {
+        let mut jobs = Vec::new();
+        for entry in self.beamlines.iter()
+        {
+            let path_template = entry.path_template.as_ref().unwrap_or(&self.path_template);
+            let schedule_template = entry.schedule_template.as_ref().or(self.schedule_template.as_ref());
+            for run in entry.runs.iter()
+            {
+                jobs.push(BatchJob
+                {
+                    beamline: entry.name.clone(),
+                    run: run.clone(),
+                    search_dir: fill_template(path_template, entry, run),
+                    num_recursive: entry.num_recursive.unwrap_or(self.num_recursive),
+                    schedule_file: schedule_template.map(|t| fill_template(t, entry, run)),
+                });
+            }
+        }
+        jobs
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn write_manifest(name: &str, contents: &str) -> String
+    {
+        let file_path = std::env::temp_dir().join(format!("mic_db_fill_{}_{}", std::process::id(), name));
+        fs::write(&file_path, contents).unwrap();
+        file_path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn shipped_manifest()
+    {
+        let manifest: Manifest = toml::from_str(include_str!("../catch_them_all.toml")).unwrap();
+        let jobs = manifest.jobs();
+        assert_eq!(jobs.len(), manifest.beamlines.iter().map(|b| b.runs.len()).sum::<usize>());
+        assert_eq!(jobs[0].beamline, "2-ID-D");
+        assert_eq!(jobs[0].run, "2025-1");
+        assert_eq!(jobs[0].search_dir, "/data1/2idd/2025-1");
+        assert_eq!(jobs[0].num_recursive, 3);
+        assert!(jobs[0].schedule_file.is_none());
+    }
+
+    #[test]
+    fn yaml_manifest_with_beamline_overrides()
+    {
+        let file_path = write_manifest("manifest.yaml", "\
+path_template: /data1/{dir}/{run}
+schedule_template: /schedules/{beamline}_{run}.json
+beamlines:
+  - name: 2-ID-E
+    runs: [2025-1, 2024-3]
+  - name: 9-ID-B,C
+    dir: bnp
+    runs: [2022-2]
+    path_template: /mnt/micdata1/{dir}/{run}/{beamline}
+    num_recursive: 1
+");
+        let manifest = Manifest::load(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        let jobs = manifest.jobs();
+        assert_eq!(jobs.len(), 3);
+        // without dir the beamline name is used
+        assert_eq!(jobs[0].search_dir, "/data1/2-ID-E/2025-1");
+        assert_eq!(jobs[0].num_recursive, 2);
+        assert_eq!(jobs[1].schedule_file.as_deref(), Some("/schedules/2-ID-E_2024-3.json"));
+        assert_eq!(jobs[2].search_dir, "/mnt/micdata1/bnp/2022-2/9-ID-B,C");
+        assert_eq!(jobs[2].num_recursive, 1);
+        assert_eq!(jobs[2].schedule_file.as_deref(), Some("/schedules/9-ID-B,C_2022-2.json"));
+    }
+
+    #[test]
+    fn manifest_errors()
+    {
+        let file_path = write_manifest("manifest.json", "{}");
+        assert!(matches!(Manifest::load(&file_path), Err(ManifestError::UnknownFormat(_))));
+        fs::remove_file(&file_path).unwrap();
+        let file_path = write_manifest("manifest.toml", "path_template = \"/data1/{dir}/{run}\"\n");
+        assert!(matches!(Manifest::load(&file_path), Err(ManifestError::Toml(_))));
+        fs::remove_file(&file_path).unwrap();
+        assert!(matches!(Manifest::load("/nonexistent/manifest.toml"), Err(ManifestError::Io(_))));
+    }
+}
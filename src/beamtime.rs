@@ -96,14 +96,14 @@ struct Experimenter {
 }
 
 #[derive(Serialize, Deserialize)]
-struct Proposal {
-    gupId: Option<i32>,
-    proposalTitle: Option<String>,
-    proprietaryFlag: Option<String>,
+pub struct Proposal {
+    pub gupId: Option<i32>,
+    pub proposalTitle: Option<String>,
+    pub proprietaryFlag: Option<String>,
     pupId: Option<i32>,
     submittedDate: Option<DateTime<Utc>>,
     totalShiftsRequested: Option<i32>,
-    mailInFlag: Option<String>,
+    pub mailInFlag: Option<String>,
     proposalStatus: Option<ProposalStatus>,
     proposalType: Option<ProposalType>,
     experimenters: Option<Vec<Experimenter>>,
@@ -165,29 +165,29 @@ struct Run {
 }
 
 #[derive(Serialize, Deserialize)]
-struct Data {
-    beamtimeId: Option<i32>,
-    schedulingPeriod: Option<String>,
-    beamlineId: Option<String>,
+pub struct Data {
+    pub beamtimeId: Option<i32>,
+    pub schedulingPeriod: Option<String>,
+    pub beamlineId: Option<String>,
     customGroup: Option<String>,
-    piLastName: Option<String>,
-    timeUnitString: Option<String>,
-    requestedShifts: Option<i32>,
-    grantedShifts: Option<i32>,
-    beamlineScheduledShifts: Option<i32>,
-    totalScheduledShifts: Option<i32>,
+    pub piLastName: Option<String>,
+    pub timeUnitString: Option<String>,
+    pub requestedShifts: Option<i32>,
+    pub grantedShifts: Option<i32>,
+    pub beamlineScheduledShifts: Option<i32>,
+    pub totalScheduledShifts: Option<i32>,
     beamlineRank: Option<String>,
-    proposalTitle: Option<String>,
+    pub proposalTitle: Option<String>,
     piFirstName: Option<String>,
     piInstitution: Option<String>,
     typeDescription: Option<String>,
     localAccess: Option<String>,
-    status: Option<String>,
+    pub status: Option<String>,
     proposalType: Option<String>,
     loggedInBadgeNo: Option<String>,
     beamtime: Option<Beamtime>,
     beamline: Option<Beamline>,
-    proposal: Option<Proposal>,
+    pub proposal: Option<Proposal>,
     run: Option<Run>,
     activiityTypeName: Option<String>,
 }
@@ -212,3 +212,8 @@ pub fn parse_beamtime(json_data: &str, experimenter_lastname: &str) -> Result<()
     });
     Ok(())
 }
+
+pub fn parse_beamtime_requests(json_data: &str) -> Result<Vec<Data>, serde_json::Error>
+{
+    serde_json::from_str(json_data)
+}
@@ -1,24 +1,62 @@
-//use ndarray::{Array2};
+use ndarray::Array2;
 use walkdir::WalkDir;
 use std::fs;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
 use std::time::UNIX_EPOCH;
-//use image::{GrayImage};
+use image::GrayImage;
+use sha2::{Digest, Sha256};
+use std::io::Read;
 
+pub mod mda;
+pub mod dataset;
 
-/*
-fn array_to_image(arr: Array2<f32>) -> GrayImage 
+#[derive(Debug, Clone, Copy)]
+pub enum PngScaling
 {
-    assert!(arr.is_standard_layout());
+    MinMax,
+    // clip the given percent of the lowest and highest values
+    Percentile(f32),
+}
+
+fn percentile(sorted: &Vec<f32>, pct: f32) -> f32
+{
+    let idx = ((pct.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f32).round() as usize;
+    sorted[idx]
+}
 
+pub fn array_to_image(arr: &Array2<f32>, scaling: PngScaling) -> GrayImage 
+{
     let (height, width) = arr.dim();
-    let raw_f32 = arr.into_raw_vec();
-    let max_val  = raw_f32.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-    let min_val = raw_f32.iter().min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
+    // NaN and inf come from dividing by a zero live time, leave them black
+    let mut finite: Vec<f32> = arr.iter().cloned().filter(|x| x.is_finite()).collect();
+    let (min_val, max_val) = if finite.is_empty()
+    {
+        (0.0, 0.0)
+    }
+    else
+    {
+        finite.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        match scaling
+        {
+            PngScaling::MinMax => (finite[0], finite[finite.len() - 1]),
+            PngScaling::Percentile(pct) => (percentile(&finite, pct), percentile(&finite, 100.0 - pct)),
+        }
+    };
     let f_range = max_val - min_val;
-    let raw_1d = raw_f32.iter().map(|&x| (255.0 * (x - min_val) / f_range) as u8).collect::<Vec<u8>>();
+    let raw_1d = arr.iter().map(|&x| 
+    {
+        if !x.is_finite() || f_range <= 0.0
+        {
+            0
+        }
+        else 
+        {
+            (255.0 * ((x - min_val) / f_range).clamp(0.0, 1.0)) as u8
+        }
+    }).collect::<Vec<u8>>();
     GrayImage::from_raw(width as u32, height as u32, raw_1d).expect("ERROR: container should have the right size for the image dimensions")
 }
-*/
 
 #[derive(Debug, Clone)]
 pub struct MyFile
@@ -43,7 +81,7 @@ pub fn get_dirs(directory:&str) -> Result<Vec<Option<String>>, std::io::Error>
 {
     let mut dir_vec: Vec<Option<String>> = Vec::new();
     
-    for entry in fs::read_dir(directory).unwrap() 
+    for entry in fs::read_dir(directory)? 
     {
         if entry.is_ok()
         {
@@ -79,3 +117,246 @@ pub fn saerch_for_ext(directory:&str, extentions: &Vec<String>, found_files: &mu
         } 
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanKind
+{
+    Step,
+    Fly,
+}
+
+static FLY_SCAN_EXT: [&str; 3] = [".nc", ".h5", ".hdf5"];
+static FLY_SCAN_SKIP_DIRS: [&str; 2] = ["mda", "img.dat"];
+
+// Names of fly scan detector outputs (flyXspress, flyXMAP, netCDF, ...) found next to a mda directory
+pub struct FlyScanIndex
+{
+    file_names: BTreeSet<String>,
+}
+
+impl FlyScanIndex
+{
+    pub fn new(mda_dir: &str) -> Self
+    {
+        let mut file_names = BTreeSet::new();
+        if let Some(parent) = Path::new(mda_dir).parent()
+        {
+            if let Ok(entries) = fs::read_dir(parent)
+            {
+                for entry in entries.filter_map(|e| e.ok())
+                {
+                    let dir_name = entry.file_name().to_string_lossy().to_string();
+                    if !entry.path().is_dir() || FLY_SCAN_SKIP_DIRS.contains(&dir_name.as_str())
+                    {
+                        continue;
+                    }
+                    for sub_entry in WalkDir::new(entry.path()).max_depth(2).into_iter().filter_map(|e| e.ok())
+                    {
+                        let f_name = sub_entry.file_name().to_string_lossy().to_string();
+                        // XRF-Maps outputs of step scans (2xfm_0123.mda.h5) can be in any directory
+                        if FLY_SCAN_EXT.iter().any(|ext| f_name.ends_with(ext)) && !f_name.contains(".mda.")
+                        {
+                            file_names.insert(f_name);
+                        }
+                    }
+                }
+            }
+        }
+        FlyScanIndex { file_names: file_names }
+    }
+
+    pub fn has_files_for(&self, scan_name: &str) -> bool
+    {
+        for sep in ["_", "."]
+        {
+            let prefix = format!("{}{}", scan_name, sep);
+            if let Some(name) = self.file_names.range(prefix.clone()..).next()
+            {
+                if name.starts_with(&prefix)
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+// scan name of a raw file, ex: /data/2025-1/Smith/mda/2xfm_0123.mda -> 2xfm_0123
+pub fn get_scan_name(file_path: &str) -> Option<String>
+{
+    let f_name = Path::new(file_path).file_name()?.to_str()?;
+    let idx = f_name.find(".mda")?;
+    Some(f_name[..idx].to_string())
+}
+
+pub fn classify_scan(file_path: &str, header: Option<&mda::MdaHeader>, fly_index: &FlyScanIndex) -> ScanKind
+{
+    if let Some(scan_name) = get_scan_name(file_path)
+    {
+        if fly_index.has_files_for(&scan_name)
+        {
+            return ScanKind::Fly;
+        }
+    }
+    if let Some(header) = header
+    {
+        // fly scans drive their inner positioner in FLY mode
+        for scan in header.scans.iter()
+        {
+            if scan.positioners.iter().any(|p| p.step_mode.to_uppercase().contains("FLY"))
+            {
+                return ScanKind::Fly;
+            }
+        }
+    }
+    ScanKind::Step
+}
+
+// detector index of a XRF-Maps output, ex: 2xfm_0123.mda.h52 -> Some(2), 2xfm_0123.mda.h5 -> None
+pub fn get_detector_index(file_path: &str) -> Option<i32>
+{
+    let f_name = Path::new(file_path).file_name()?.to_str()?;
+    let idx = f_name.rfind(".h5")?;
+    f_name[idx + 3..].parse::<i32>().ok()
+}
+
+// Raw and analyzed files found for one PI directory
+pub struct DatasetDir
+{
+    pub raw_files: Vec<MyFile>,
+    // analyzed files keyed by the scan name of their raw file
+    pub analyzed_files: HashMap<String, Vec<MyFile>>,
+    pub fly_index: FlyScanIndex,
+    // mda headers keyed by raw file name, read once for attribution and ingestion
+    headers: HashMap<String, Result<mda::MdaHeader, String>>,
+}
+
+impl DatasetDir
+{
+    pub fn new(mda_dir: &str, raw_files: Vec<MyFile>, analyzed_dir: &str, analyzed_ext: &Vec<String>) -> Self
+    {
+        let mut found_analyzed = Vec::new();
+        if Path::new(analyzed_dir).is_dir()
+        {
+            saerch_for_ext(analyzed_dir, analyzed_ext, &mut found_analyzed);
+        }
+        let mut analyzed_files: HashMap<String, Vec<MyFile>> = HashMap::new();
+        for analyzed_file in found_analyzed
+        {
+            if let Some(scan_name) = get_scan_name(&analyzed_file.name)
+            {
+                analyzed_files.entry(scan_name).or_insert_with(Vec::new).push(analyzed_file);
+            }
+        }
+        let mut headers = HashMap::new();
+        for raw_file in raw_files.iter()
+        {
+            headers.insert(raw_file.name.clone(), mda::read_header(&raw_file.name).map_err(|e| format!("{:?}", e)));
+        }
+        DatasetDir
+        {
+            raw_files: raw_files,
+            analyzed_files: analyzed_files,
+            fly_index: FlyScanIndex::new(mda_dir),
+            headers: headers,
+        }
+    }
+
+    // Err is why the header could not be read
+    pub fn get_header(&self, raw_file_path: &str) -> Result<&mda::MdaHeader, String>
+    {
+        match self.headers.get(raw_file_path)
+        {
+            Some(Ok(header)) => Ok(header),
+            Some(Err(e)) => Err(e.clone()),
+            None => Err(String::from("not a raw file of this directory")),
+        }
+    }
+
+    // Scan start time from the mda header, None if the header can not be read or has no time.
+    // File times are not used, copies and rsync change them.
+    pub fn get_acquisition_time(&self, raw_file_path: &str) -> Option<std::time::SystemTime>
+    {
+        self.get_header(raw_file_path).ok().and_then(|header| header.start_time())
+    }
+
+    pub fn get_analyzed_files(&self, raw_file_path: &str) -> &[MyFile]
+    {
+        match get_scan_name(raw_file_path).and_then(|scan_name| self.analyzed_files.get(&scan_name))
+        {
+            Some(files) => files,
+            None => &[],
+        }
+    }
+}
+
+// sha256 of the file contents as a hex string
+pub fn file_checksum(file_path: &str) -> Result<String, std::io::Error>
+{
+    let mut file = fs::File::open(file_path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop
+    {
+        let n = file.read(&mut buf)?;
+        if n == 0
+        {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn touch(root: &Path, rel_path: &str)
+    {
+        let path = root.join(rel_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+
+    #[test]
+    fn scan_and_detector_names()
+    {
+        assert_eq!(get_scan_name("/data/2025-1/Smith/mda/2xfm_0123.mda"), Some(String::from("2xfm_0123")));
+        assert_eq!(get_scan_name("/data/2025-1/Smith/img.dat/2xfm_0123.mda.h52"), Some(String::from("2xfm_0123")));
+        assert_eq!(get_scan_name("/data/2025-1/Smith/img.dat/notes.txt"), None);
+        assert_eq!(get_detector_index("/data/img.dat/2xfm_0123.mda.h52"), Some(2));
+        assert_eq!(get_detector_index("/data/img.dat/2xfm_0123.mda.h5"), None);
+        assert_eq!(get_detector_index("/data/img.dat/2xfm_0123.mda"), None);
+    }
+
+    #[test]
+    fn fly_scans_from_sibling_files()
+    {
+        let root = std::env::temp_dir().join(format!("mic_db_fill_{}_fly", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for name in ["2xfm_0001", "2xfm_0002", "2xfm_0003", "2xfm_0004"]
+        {
+            touch(&root, &format!("mda/{}.mda", name));
+        }
+        // fly scan detector outputs
+        touch(&root, "flyXspress/2xfm_0001_0.h5");
+        touch(&root, "flyXMAP/2xfm_0004.nc");
+        // XRF-Maps outputs of step scans outside img.dat
+        touch(&root, "output/2xfm_0002.mda.h5");
+        touch(&root, "img.dat_old/2xfm_0002.mda.h50");
+        touch(&root, "img.dat.bak/2xfm_0003.mda.h51");
+        touch(&root, "img.dat/2xfm_0003.mda.h5");
+        let mda_dir = root.join("mda");
+        let fly_index = FlyScanIndex::new(mda_dir.to_str().unwrap());
+        let kinds: Vec<ScanKind> = ["2xfm_0001", "2xfm_0002", "2xfm_0003", "2xfm_0004"].iter()
+            .map(|name| classify_scan(mda_dir.join(format!("{}.mda", name)).to_str().unwrap(), None, &fly_index))
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(kinds, vec![ScanKind::Fly, ScanKind::Step, ScanKind::Step, ScanKind::Fly]);
+        // scan numbers sharing a prefix are different scans
+        assert!(!fly_index.has_files_for("2xfm_000"));
+    }
+}
@@ -2,7 +2,10 @@
 //use hdf5::filters::blosc_set_nthreads;
 use hdf5::{File, Result};
 use ndarray::{Array2, s};
+use std::path::Path;
+use super::{array_to_image, PngScaling};
 
+#[derive(Debug)]
 enum AnalysisType
 {
     NNLS,
@@ -65,13 +68,30 @@ impl XrfDataset
                 let slice = s![i, .., ..];
                 let data: Array2<f32> = ds_counts.read_slice(slice)?;
                 analyzed_counts.counts_data.push(data);
-                //println!("{}", chan_names[i]);
-                //let img = array_to_image(data);
-                //img.save(format!("/Users/aglowacki/data/tmp/{}.png", chan_names[i])).unwrap_or();
             }
             self.analyzed_data.push(analyzed_counts);
         }
         Ok(())
     }
-}
 
+    // Save one png per element and analysis type, returns (element, png path) pairs
+    pub fn export_counts_png(&self, output_dir: &Path, scaling: PngScaling) -> std::result::Result<Vec<(String, String)>, image::ImageError>
+    {
+        let mut saved = Vec::new();
+        let file_name = Path::new(&self.path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+        std::fs::create_dir_all(output_dir)?;
+        for analyzed_counts in self.analyzed_data.iter()
+        {
+            for (chan_name, data) in analyzed_counts.channel_names.iter().zip(analyzed_counts.counts_data.iter())
+            {
+                let element = chan_name.trim().to_string();
+                let safe_element: String = element.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+                let png_path = output_dir.join(format!("{}_{:?}_{}.png", file_name, analyzed_counts.analysis_type, safe_element));
+                let img = array_to_image(data, scaling);
+                img.save(&png_path)?;
+                saved.push((element, png_path.to_string_lossy().to_string()));
+            }
+        }
+        Ok(saved)
+    }
+}
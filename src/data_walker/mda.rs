@@ -0,0 +1,459 @@
+// Reader for the EPICS saveData MDA binary format.
+// All values are XDR encoded (big endian, every item padded to 4 bytes).
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use chrono::{Local, NaiveDateTime, TimeZone};
+
+#[derive(Debug, Clone)]
+pub struct MdaPositioner
+{
+    pub number: i32,
+    pub name: String,
+    pub description: String,
+    pub step_mode: String,
+    pub unit: String,
+    pub readback_name: String,
+    pub readback_description: String,
+    pub readback_unit: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MdaDetector
+{
+    pub number: i32,
+    pub name: String,
+    pub description: String,
+    pub unit: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MdaScanHeader
+{
+    pub rank: i32,
+    pub requested_points: i32,
+    pub current_point: i32,
+    pub name: String,
+    pub time: String,
+    pub positioners: Vec<MdaPositioner>,
+    pub detectors: Vec<MdaDetector>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MdaHeader
+{
+    pub version: f32,
+    pub scan_number: i32,
+    pub rank: i32,
+    pub dimensions: Vec<i32>,
+    pub is_regular: bool,
+    // one header per dimension, outer most scan first
+    pub scans: Vec<MdaScanHeader>,
+}
+
+impl MdaHeader
+{
+    pub fn requested_points(&self) -> Vec<i32>
+    {
+        self.scans.iter().map(|s| s.requested_points).collect()
+    }
+
+    pub fn acquired_points(&self) -> Vec<i32>
+    {
+        self.scans.iter().map(|s| s.current_point).collect()
+    }
+
+    // Start time of the outer most scan, saveData writes it in the ioc local time
+    pub fn start_time(&self) -> Option<std::time::SystemTime>
+    {
+        let scan = self.scans.first()?;
+        parse_mda_time(&scan.time)
+    }
+}
+
+struct XdrReader<R: Read + Seek>
+{
+    inner: R,
+}
+
+impl<R: Read + Seek> XdrReader<R>
+{
+    fn new(inner: R) -> Self
+    {
+        XdrReader { inner: inner }
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32>
+    {
+        let mut buf = [0u8; 4];
+        self.inner.read_exact(&mut buf)?;
+        Ok(i32::from_be_bytes(buf))
+    }
+
+    // xdr shorts take a full 4 byte word
+    fn read_i16(&mut self) -> io::Result<i16>
+    {
+        Ok(self.read_i32()? as i16)
+    }
+
+    fn read_f32(&mut self) -> io::Result<f32>
+    {
+        let mut buf = [0u8; 4];
+        self.inner.read_exact(&mut buf)?;
+        Ok(f32::from_be_bytes(buf))
+    }
+
+    fn read_count(&mut self, what: &str) -> io::Result<usize>
+    {
+        let count = self.read_i32()?;
+        if count < 0
+        {
+            return Err(invalid_data(&format!("negative {} count {}", what, count)));
+        }
+        Ok(count as usize)
+    }
+
+    // xdr opaque data: length followed by the bytes padded to 4
+    fn read_opaque(&mut self) -> io::Result<Vec<u8>>
+    {
+        let len = self.read_count("byte")?;
+        // a corrupt length must not allocate gigabytes before the end of the file is hit
+        let mut buf = Vec::new();
+        (&mut self.inner).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() < len
+        {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "string past the end of the file"));
+        }
+        let pad = (4 - len % 4) % 4;
+        if pad > 0
+        {
+            self.inner.seek(SeekFrom::Current(pad as i64))?;
+        }
+        Ok(buf)
+    }
+
+    // MDA strings are prefixed by their length and only followed by a xdr string if non empty
+    fn read_mda_string(&mut self) -> io::Result<String>
+    {
+        let len = self.read_i32()?;
+        if len <= 0
+        {
+            return Ok(String::new());
+        }
+        let buf = self.read_opaque()?;
+        Ok(String::from_utf8_lossy(&buf).trim_end_matches('\0').trim().to_string())
+    }
+
+    fn seek(&mut self, offset: u64) -> io::Result<()>
+    {
+        self.inner.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+pub fn parse_mda_time(time_str: &str) -> Option<std::time::SystemTime>
+{
+    // saveData format is "%b %d, %Y %H:%M:%S.%09f"
+    let trimmed = time_str.trim();
+    let naive = NaiveDateTime::parse_from_str(trimmed, "%b %d, %Y %H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(trimmed, "%b %d, %Y %H:%M:%S"))
+        .ok()?;
+    let local = Local.from_local_datetime(&naive).earliest()?;
+    Some(local.into())
+}
+
+fn read_scan_header<R: Read + Seek>(reader: &mut XdrReader<R>) -> io::Result<(MdaScanHeader, Vec<i32>)>
+{
+    let rank = reader.read_i16()? as i32;
+    let requested_points = reader.read_i32()?;
+    let current_point = reader.read_i32()?;
+    let mut lower_scan_offsets = Vec::new();
+    if rank > 1
+    {
+        if requested_points < 0
+        {
+            return Err(invalid_data(&format!("negative number of points {}", requested_points)));
+        }
+        for _ in 0..requested_points
+        {
+            lower_scan_offsets.push(reader.read_i32()?);
+        }
+    }
+    let name = reader.read_mda_string()?;
+    let time = reader.read_mda_string()?;
+    let num_positioners = reader.read_count("positioner")?;
+    let num_detectors = reader.read_count("detector")?;
+    // triggers follow the detectors and are not needed
+    reader.read_count("trigger")?;
+
+    let mut positioners = Vec::new();
+    for _ in 0..num_positioners
+    {
+        positioners.push(MdaPositioner
+        {
+            number: reader.read_i16()? as i32,
+            name: reader.read_mda_string()?,
+            description: reader.read_mda_string()?,
+            step_mode: reader.read_mda_string()?,
+            unit: reader.read_mda_string()?,
+            readback_name: reader.read_mda_string()?,
+            readback_description: reader.read_mda_string()?,
+            readback_unit: reader.read_mda_string()?,
+        });
+    }
+    let mut detectors = Vec::new();
+    for _ in 0..num_detectors
+    {
+        detectors.push(MdaDetector
+        {
+            number: reader.read_i16()? as i32,
+            name: reader.read_mda_string()?,
+            description: reader.read_mda_string()?,
+            unit: reader.read_mda_string()?,
+        });
+    }
+    let header = MdaScanHeader { rank: rank, requested_points: requested_points, current_point: current_point, name: name, time: time, positioners: positioners, detectors: detectors };
+    Ok((header, lower_scan_offsets))
+}
+
+// Reads the file header and the header of the first scan of every dimension.
+// Positioner and detector data, triggers and extra PVs are skipped.
+pub fn read_header(file_path: &str) -> io::Result<MdaHeader>
+{
+    let file = File::open(file_path)?;
+    read_header_from(BufReader::new(file))
+}
+
+fn read_header_from<R: Read + Seek>(inner: R) -> io::Result<MdaHeader>
+{
+    let mut reader = XdrReader::new(inner);
+
+    let version = reader.read_f32()?;
+    let scan_number = reader.read_i32()?;
+    let rank = reader.read_i16()? as i32;
+    if rank < 1
+    {
+        return Err(invalid_data(&format!("invalid scan rank {}", rank)));
+    }
+    let mut dimensions = Vec::new();
+    for _ in 0..rank
+    {
+        dimensions.push(reader.read_i32()?);
+    }
+    let is_regular = reader.read_i16()? != 0;
+    // offset of the extra PVs
+    reader.read_i32()?;
+
+    let mut scans = Vec::new();
+    loop
+    {
+        let (scan, lower_offsets) = read_scan_header(&mut reader)?;
+        let scan_rank = scan.rank;
+        scans.push(scan);
+        if scan_rank <= 1
+        {
+            break;
+        }
+        // follow the first acquired sub scan, an aborted scan may not have any
+        match lower_offsets.iter().find(|&&offset| offset > 0)
+        {
+            Some(&offset) => reader.seek(offset as u64)?,
+            None => break,
+        }
+    }
+
+    Ok(MdaHeader { version: version, scan_number: scan_number, rank: rank, dimensions: dimensions, is_regular: is_regular, scans: scans })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::Cursor;
+
+    // XDR writer for hand made mda files
+    struct Xdr
+    {
+        buf: Vec<u8>,
+    }
+
+    impl Xdr
+    {
+        fn i32(&mut self, value: i32) -> &mut Self
+        {
+            self.buf.extend_from_slice(&value.to_be_bytes());
+            self
+        }
+
+        fn f32(&mut self, value: f32) -> &mut Self
+        {
+            self.buf.extend_from_slice(&value.to_be_bytes());
+            self
+        }
+
+        fn string(&mut self, value: &str) -> &mut Self
+        {
+            self.i32(value.len() as i32);
+            if !value.is_empty()
+            {
+                self.i32(value.len() as i32);
+                self.buf.extend_from_slice(value.as_bytes());
+                self.buf.resize(self.buf.len() + (4 - value.len() % 4) % 4, 0);
+            }
+            self
+        }
+
+        fn file_header(&mut self, dimensions: &[i32], extra_offset: i32) -> &mut Self
+        {
+            self.f32(1.4).i32(123).i32(dimensions.len() as i32);
+            for dimension in dimensions
+            {
+                self.i32(*dimension);
+            }
+            self.i32(1).i32(extra_offset)
+        }
+
+        // scan header with one positioner, one detector and optionally one trigger.
+        // Returns the position of the lower scan offsets to fill in later.
+        fn scan_header(&mut self, rank: i32, requested: i32, current: i32, step_mode: &str, with_trigger: bool) -> usize
+        {
+            self.i32(rank).i32(requested).i32(current);
+            let offsets_pos = self.buf.len();
+            if rank > 1
+            {
+                for _ in 0..requested
+                {
+                    self.i32(0);
+                }
+            }
+            self.string(&format!("2xfm:scan{}", rank)).string("Feb 04, 2025 13:22:11.123456789");
+            self.i32(1).i32(1).i32(with_trigger as i32);
+            self.i32(1).string("2xfm:m1").string("sample x").string(step_mode).string("mm").string("2xfm:m1.RBV").string("").string("mm");
+            self.i32(1).string("2xfm:det1").string("counts").string("");
+            if with_trigger
+            {
+                self.i32(1).string("2xfm:scan1.EXSC").f32(1.0);
+            }
+            offsets_pos
+        }
+
+        fn set_i32(&mut self, pos: usize, value: i32)
+        {
+            self.buf[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    // 2D scan of 3 lines of 5 points with `acquired` lines, inner scan headers for each acquired line
+    fn rank2_file(acquired: i32, with_trigger: bool) -> Vec<u8>
+    {
+        let mut xdr = Xdr { buf: Vec::new() };
+        xdr.file_header(&[3, 5], 0);
+        let offsets_pos = xdr.scan_header(2, 3, acquired, "LINEAR", with_trigger);
+        for line in 0..acquired
+        {
+            let offset = xdr.buf.len() as i32;
+            xdr.set_i32(offsets_pos + 4 * line as usize, offset);
+            xdr.scan_header(1, 5, 5, "FLY", with_trigger);
+        }
+        xdr.buf
+    }
+
+    fn read(buf: &[u8]) -> io::Result<MdaHeader>
+    {
+        read_header_from(Cursor::new(buf.to_vec()))
+    }
+
+    #[test]
+    fn rank1_scan()
+    {
+        let mut xdr = Xdr { buf: Vec::new() };
+        xdr.file_header(&[100], 0);
+        xdr.scan_header(1, 100, 100, "LINEAR", false);
+        let header = read(&xdr.buf).unwrap();
+        assert_eq!((header.scan_number, header.rank, header.is_regular), (123, 1, true));
+        assert_eq!(header.dimensions, vec![100]);
+        assert_eq!(header.scans.len(), 1);
+        assert_eq!(header.requested_points(), vec![100]);
+        assert_eq!(header.acquired_points(), vec![100]);
+        let scan = &header.scans[0];
+        assert_eq!(scan.name, "2xfm:scan1");
+        assert_eq!((scan.positioners[0].name.as_str(), scan.positioners[0].step_mode.as_str(), scan.positioners[0].unit.as_str()), ("2xfm:m1", "LINEAR", "mm"));
+        assert_eq!(scan.positioners[0].readback_description, "");
+        assert_eq!((scan.detectors[0].name.as_str(), scan.detectors[0].description.as_str()), ("2xfm:det1", "counts"));
+        let expected = Local.from_local_datetime(&NaiveDateTime::parse_from_str("2025-02-04 13:22:11.123456789", "%Y-%m-%d %H:%M:%S%.f").unwrap()).earliest().unwrap();
+        assert_eq!(header.start_time(), Some(expected.into()));
+    }
+
+    #[test]
+    fn rank2_scan()
+    {
+        let header = read(&rank2_file(3, false)).unwrap();
+        assert_eq!(header.dimensions, vec![3, 5]);
+        assert_eq!(header.scans.len(), 2);
+        assert_eq!((header.scans[0].rank, header.scans[1].rank), (2, 1));
+        assert_eq!(header.scans[1].positioners[0].step_mode, "FLY");
+        assert_eq!(header.requested_points(), vec![3, 5]);
+        assert_eq!(header.acquired_points(), vec![3, 5]);
+    }
+
+    #[test]
+    fn aborted_scan()
+    {
+        let header = read(&rank2_file(2, false)).unwrap();
+        assert_eq!(header.dimensions, vec![3, 5]);
+        assert_eq!(header.requested_points(), vec![3, 5]);
+        assert_eq!(header.acquired_points(), vec![2, 5]);
+        // aborted before the first line, the dimensions still come from the file header
+        let header = read(&rank2_file(0, false)).unwrap();
+        assert_eq!(header.dimensions, vec![3, 5]);
+        assert_eq!(header.scans.len(), 1);
+        assert_eq!(header.acquired_points(), vec![0]);
+    }
+
+    #[test]
+    fn truncated_file()
+    {
+        let buf = rank2_file(1, false);
+        for len in 0..buf.len()
+        {
+            assert!(read(&buf[..len]).is_err(), "read {} of {} bytes", len, buf.len());
+        }
+        // a corrupt string length past the end of the file
+        let mut xdr = Xdr { buf: Vec::new() };
+        xdr.file_header(&[10], 0);
+        xdr.i32(1).i32(10).i32(10).i32(i32::MAX).i32(i32::MAX);
+        assert!(read(&xdr.buf).is_err());
+        // negative counts
+        let mut xdr = Xdr { buf: Vec::new() };
+        xdr.file_header(&[10], 0);
+        xdr.i32(1).i32(10).i32(10).string("").string("").i32(-1);
+        assert!(read(&xdr.buf).is_err());
+        let mut xdr = Xdr { buf: Vec::new() };
+        xdr.file_header(&[], 0);
+        assert!(read(&xdr.buf).is_err());
+    }
+
+    #[test]
+    fn extra_pvs_and_triggers_are_skipped()
+    {
+        let mut buf = rank2_file(3, true);
+        let extra_offset = buf.len() as i32;
+        // after version, scan number, rank, two dimensions and the regular flag
+        buf[24..28].copy_from_slice(&extra_offset.to_be_bytes());
+        let mut xdr = Xdr { buf: buf };
+        // one string and one double extra pv
+        xdr.i32(2);
+        xdr.string("2xfm:userStringCalc1").string("note").i32(0).string("sample A");
+        xdr.string("2xfm:energy").string("energy").i32(34).i32(1).string("keV");
+        xdr.buf.extend_from_slice(&10.5f64.to_be_bytes());
+        let header = read(&xdr.buf).unwrap();
+        assert!(header.is_regular);
+        assert_eq!(header.dimensions, vec![3, 5]);
+        assert_eq!(header.acquired_points(), vec![3, 5]);
+        assert_eq!(header.scans[1].detectors[0].name, "2xfm:det1");
+    }
+}
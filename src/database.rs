@@ -1,10 +1,20 @@
 use postgres::Client;
+use serde::{Serialize, Serializer};
+use chrono::{DateTime, Utc};
 //use chrono::{DateTime, Utc, NaiveDateTime};
 use crate::activity;
+use crate::beamtime;
+use crate::data_walker::mda;
 
 
+fn serialize_timestamp<S: Serializer>(timestamp: &std::time::SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+{
+    let date_time: DateTime<Utc> = (*timestamp).into();
+    serializer.serialize_str(&date_time.to_rfc3339())
+}
+
 //--------------------------------------------------------------
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UserAccessControl
 {
     id: i32,
@@ -19,8 +29,8 @@ impl UserAccessControl
         UserAccessControl { id: my_id, level: String::from(user_access_control), description: String::from(descr) }
     }
 }
-#[derive(Debug, Clone)]
-pub struct User 
+#[derive(Debug, Clone, Serialize)]
+pub struct User
 {
     pub badge: i32,
     pub username: String,
@@ -31,18 +41,53 @@ pub struct User
     pub user_access_control: UserAccessControl,
 }
 
+// Experimenters from the schedule that can not be stored as users
+#[derive(Debug)]
+pub enum UserError
+{
+    NoEmail(String),
+    BadgeParse(String),
+}
+
+impl std::fmt::Display for UserError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            UserError::NoEmail(message) | UserError::BadgeParse(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 impl User 
 {
     pub fn from_db(row: &postgres::Row) -> Self 
     {
         User { badge: row.get(0), username: row.get(1), first_name: row.get(2), last_name: row.get(3), institution: row.get(4), email: row.get(5), user_access_control: UserAccessControl::new(row.get(6), row.get(7), row.get(8)) }
     }
-    pub fn from_experimenter(experimenter: &activity::Experimenter, uac: &UserAccessControl) -> Self 
+    // the email is also the username
+    pub fn from_experimenter(experimenter: &activity::Experimenter, uac: &UserAccessControl) -> Result<Self, UserError> 
+    {
+        let badge = experimenter.badge.trim().parse::<i32>().map_err(|_| UserError::BadgeParse(format!("badge {:?} of {} {} is not a number", experimenter.badge, experimenter.firstName, experimenter.lastName)))?;
+        let email = match experimenter.email.as_deref().map(|e| e.trim())
+        {
+            Some(email) if !email.is_empty() => email.to_string(),
+            _ => return Err(UserError::NoEmail(format!("{} {} has no email", experimenter.firstName, experimenter.lastName))),
+        };
+        Ok(User { badge: badge, username: email.clone(), first_name: experimenter.firstName.clone(), last_name: experimenter.lastName.clone(), institution: experimenter.institution.clone(), email: email, user_access_control: uac.clone() })
+    }
+    // local contact of an activity, None without a numeric badge
+    pub fn from_local_contact(contact: &activity::UserType, uac: &UserAccessControl) -> Option<Self> 
     {
-        User { badge: experimenter.badge.parse::<i32>().unwrap(), username: experimenter.email.clone().unwrap(), first_name: experimenter.firstName.clone(), last_name: experimenter.lastName.clone(), institution: experimenter.institution.clone(), email: experimenter.email.clone().unwrap(), user_access_control: uac.clone() }
+        let badge = contact.badgeNo.as_ref()?.trim().parse::<i32>().ok()?;
+        let email = contact.email.clone().unwrap_or_default();
+        // the badge keeps usernames unique when the schedule has neither a username nor an email
+        let username = contact.userName.clone().filter(|u| !u.trim().is_empty()).or(Some(email.clone()).filter(|e| !e.trim().is_empty())).unwrap_or(badge.to_string());
+        Some(User { badge: badge, username: username, first_name: contact.firstName.clone().unwrap_or_default(), last_name: contact.lastName.clone().unwrap_or_default(), institution: String::new(), email: email, user_access_control: uac.clone() })
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Proposal
 {
     pub id: i32,
@@ -58,7 +103,51 @@ impl Proposal
     {
         Proposal { id: proposal.gupId.unwrap(), title: proposal.proposalTitle.clone().unwrap(), proprietaryFlag: proposal.proprietaryFlag.clone().unwrap(), mailInFlag: proposal.mailInFlag.clone().unwrap(), status: String::from("Done") }
     }
+    pub fn from_beamtime_request(request: &beamtime::Data) -> Option<Self> 
+    {
+        let proposal = request.proposal.as_ref();
+        let id = proposal.and_then(|p| p.gupId)?;
+        let title = proposal.and_then(|p| p.proposalTitle.clone()).or(request.proposalTitle.clone()).unwrap_or_default();
+        let proprietary_flag = proposal.and_then(|p| p.proprietaryFlag.clone()).unwrap_or(String::from("N"));
+        let mail_in_flag = proposal.and_then(|p| p.mailInFlag.clone()).unwrap_or(String::from("N"));
+        Some(Proposal { id: id, title: title, proprietaryFlag: proprietary_flag, mailInFlag: mail_in_flag, status: String::from("Done") })
+    }
 }
+#[derive(Debug, Clone)]
+pub struct BeamtimeRequest
+{
+    beamtime_id: i32,
+    proposal_id: i32,
+    syncotron_run_id: i32,
+    beamline_id: i32,
+    requested_shifts: Option<i32>,
+    granted_shifts: Option<i32>,
+    scheduled_shifts: Option<i32>,
+    total_scheduled_shifts: Option<i32>,
+    time_unit: Option<String>,
+    status: Option<String>,
+}
+
+impl BeamtimeRequest 
+{
+    pub fn from_beamtime_request(request: &beamtime::Data, proposal_id: i32, syncotron_run_id: i32, beamline_id: i32) -> Option<Self> 
+    {
+        Some(BeamtimeRequest 
+        { 
+            beamtime_id: request.beamtimeId?, 
+            proposal_id: proposal_id, 
+            syncotron_run_id: syncotron_run_id, 
+            beamline_id: beamline_id, 
+            requested_shifts: request.requestedShifts, 
+            granted_shifts: request.grantedShifts, 
+            scheduled_shifts: request.beamlineScheduledShifts, 
+            total_scheduled_shifts: request.totalScheduledShifts, 
+            time_unit: request.timeUnitString.clone(), 
+            status: request.status.clone() 
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataStore
 {
@@ -109,6 +198,14 @@ pub struct ScanType
     description: String,
 }
 
+impl ScanType
+{
+    pub fn get_id(&self) -> i32
+    {
+        return self.id;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SyncRun
 {
@@ -126,7 +223,7 @@ impl SyncRun
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Dataset
 {
     id: i32,
@@ -134,20 +231,133 @@ pub struct Dataset
     syncotron_run_id: i32,
     scan_type_id: i32,
     path: String,
+    #[serde(serialize_with = "serialize_timestamp")]
     acquisition_timestamp: std::time::SystemTime,
+    scan_rank: Option<i32>,
+    scan_dimensions: Option<Vec<i32>>,
+    scan_points: Option<Vec<i32>>,
+    checksum: Option<String>,
 }
 
 impl Dataset
 {
     pub fn new(beamline_id: i32, syncotron_run_id: i32, scan_type_id: i32, ppath: &str, acquisition_timestamp: std::time::SystemTime) -> Self 
     {
-        Dataset { id: 0, beamline_id: beamline_id, syncotron_run_id: syncotron_run_id, scan_type_id: scan_type_id, path: ppath.to_owned(), acquisition_timestamp: acquisition_timestamp }
+        Dataset { id: 0, beamline_id: beamline_id, syncotron_run_id: syncotron_run_id, scan_type_id: scan_type_id, path: ppath.to_owned(), acquisition_timestamp: acquisition_timestamp, scan_rank: None, scan_dimensions: None, scan_points: None, checksum: None }
+    }
+
+    pub fn from_db(row: &postgres::Row) -> Self 
+    {
+        Dataset { id: row.get(0), beamline_id: row.get(1), syncotron_run_id: row.get(2), scan_type_id: row.get(3), path: row.get(4), acquisition_timestamp: row.get(5), scan_rank: row.get(6), scan_dimensions: row.get(7), scan_points: row.get(8), checksum: row.get(9) }
+    }
+
+    pub fn from_mda_header(beamline_id: i32, syncotron_run_id: i32, scan_type_id: i32, ppath: &str, file_timestamp: std::time::SystemTime, header: &mda::MdaHeader) -> Self 
+    {
+        let mut dataset = Dataset::new(beamline_id, syncotron_run_id, scan_type_id, ppath, header.start_time().unwrap_or(file_timestamp));
+        dataset.scan_rank = Some(header.rank);
+        // size of every dimension from the file header, an aborted scan may be missing inner scan headers
+        dataset.scan_dimensions = Some(header.dimensions.clone());
+        dataset.scan_points = Some(header.acquired_points());
+        dataset
     }
 
     pub fn get_id(&self) -> i32
     {
         return self.id;
     }
+
+    pub fn set_id(&mut self, id: i32)
+    {
+        self.id = id;
+    }
+
+    pub fn get_path(&self) -> &str
+    {
+        return &self.path;
+    }
+
+    pub fn get_checksum(&self) -> Option<&str>
+    {
+        return self.checksum.as_deref();
+    }
+
+    pub fn set_checksum(&mut self, checksum: String)
+    {
+        self.checksum = Some(checksum);
+    }
+
+    // human readable list of what changed compared to the row already in the database
+    pub fn differences(&self, existing: &Dataset) -> Vec<String>
+    {
+        let mut diffs = Vec::new();
+        if self.path != existing.path
+        {
+            diffs.push(format!("path {} -> {}", existing.path, self.path));
+        }
+        // postgres keeps microseconds
+        let old_time: DateTime<Utc> = existing.acquisition_timestamp.into();
+        let new_time: DateTime<Utc> = self.acquisition_timestamp.into();
+        if old_time.timestamp_micros() != new_time.timestamp_micros()
+        {
+            diffs.push(format!("acquisition_timestamp {} -> {}", old_time.to_rfc3339(), new_time.to_rfc3339()));
+        }
+        if self.scan_type_id != existing.scan_type_id
+        {
+            diffs.push(format!("scan_type_id {} -> {}", existing.scan_type_id, self.scan_type_id));
+        }
+        if self.scan_rank != existing.scan_rank
+        {
+            diffs.push(format!("scan_rank {:?} -> {:?}", existing.scan_rank, self.scan_rank));
+        }
+        if self.scan_dimensions != existing.scan_dimensions
+        {
+            diffs.push(format!("scan_dimensions {:?} -> {:?}", existing.scan_dimensions, self.scan_dimensions));
+        }
+        if self.scan_points != existing.scan_points
+        {
+            diffs.push(format!("scan_points {:?} -> {:?}", existing.scan_points, self.scan_points));
+        }
+        if self.checksum.is_some() && self.checksum != existing.checksum
+        {
+            diffs.push(format!("checksum {:?} -> {:?}", existing.checksum, self.checksum));
+        }
+        diffs
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalyzedFile
+{
+    dataset_id: i32,
+    path: String,
+    detector: Option<i32>,
+    #[serde(serialize_with = "serialize_timestamp")]
+    processed_timestamp: std::time::SystemTime,
+}
+
+impl AnalyzedFile
+{
+    pub fn new(dataset_id: i32, ppath: &str, detector: Option<i32>, processed_timestamp: std::time::SystemTime) -> Self 
+    {
+        AnalyzedFile { dataset_id: dataset_id, path: ppath.to_owned(), detector: detector, processed_timestamp: processed_timestamp }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetImage
+{
+    dataset_id: i32,
+    analyzed_path: String,
+    element: String,
+    path: String,
+}
+
+impl DatasetImage
+{
+    pub fn new(dataset_id: i32, analyzed_path: &str, element: &str, ppath: &str) -> Self 
+    {
+        DatasetImage { dataset_id: dataset_id, analyzed_path: analyzed_path.to_owned(), element: element.to_owned(), path: ppath.to_owned() }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -165,18 +375,18 @@ impl ExperimenterRole
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct Experimenter
 {
     dataset_id: i32,// integer REFERENCES datasets (id),
     user_badge: i32, //integer REFERENCES users (badge),
-    proposal_id: i32, //integer REFERENCES proposals (id),
+    proposal_id: Option<i32>, //integer REFERENCES proposals (id), NULL for staff data
     experiment_role_id: i32 //integer REFERENCES experiment_roles (id)
 }
 
 impl Experimenter
 {
-    pub fn new(dataset_id: i32, user_badge: i32, proposal_id: i32, experiment_role_id: i32) -> Self 
+    pub fn new(dataset_id: i32, user_badge: i32, proposal_id: Option<i32>, experiment_role_id: i32) -> Self 
     {
         Experimenter { dataset_id: dataset_id, user_badge: user_badge, proposal_id: proposal_id, experiment_role_id: experiment_role_id }
     }
@@ -225,6 +435,24 @@ pub fn get_user_by_badge(db_client: &mut Client, badge: u32) -> Result<Option<Us
     Ok(None)
 }
 
+// Match on path first, a matching checksum finds files that were moved since the last run
+// Dataset with this path first, then the datasets with the same checksum
+pub fn get_datasets_by_path(db_client: &mut Client, path: &str, checksum: Option<&str>) -> Result<Vec<Dataset>, postgres::Error> 
+{
+    let query = "SELECT id, beamline_id, syncotron_run_id, scan_type_id, path, acquisition_timestamp, scan_rank, scan_dimensions, scan_points, checksum FROM datasets WHERE path = $1 OR ($2::text IS NOT NULL AND checksum = $2) ORDER BY (path = $1) DESC, id";
+    Ok(db_client.query(query, &[&path, &checksum])?.iter().map(Dataset::from_db).collect())
+}
+
+pub fn user_exists(db_client: &mut Client, badge: i32) -> Result<bool, postgres::Error> 
+{
+    Ok(db_client.query_one("SELECT EXISTS (SELECT 1 FROM users WHERE badge = $1)", &[&badge])?.get(0))
+}
+
+pub fn proposal_exists(db_client: &mut Client, proposal_id: i32) -> Result<bool, postgres::Error> 
+{
+    Ok(db_client.query_one("SELECT EXISTS (SELECT 1 FROM proposals WHERE id = $1)", &[&proposal_id])?.get(0))
+}
+
 pub fn get_all_staff_users(db_client: &mut Client, staff: &mut Vec<User>) -> Result<(), postgres::Error> 
 {
     for row in db_client.query("SELECT u.badge, u.username, u.first_name, u.last_name, u.institution, u.email, uac.id, uac.level, uac.description FROM users u INNER JOIN user_access_control uac ON u.user_access_control_id = uac.id WHERE uac.level = 'Staff';", &[])? 
@@ -325,6 +553,70 @@ pub fn get_beamlines(db_client: &mut Client, beamlines: &mut std::collections::H
 
 // ----------- Insert Functions -----------------------------
 
+// Everything the ingestion writes goes through this trait so it can be recorded instead (dry run)
+pub trait DbWriter
+{
+    fn begin(&mut self) -> Result<(), postgres::Error>;
+    fn commit(&mut self) -> Result<(), postgres::Error>;
+    fn rollback(&mut self) -> Result<(), postgres::Error>;
+    fn get_datasets_by_path(&mut self, path: &str, checksum: Option<&str>) -> Result<Vec<Dataset>, postgres::Error>;
+    fn update_dataset(&mut self, dataset_id: i32, dataset: &Dataset) -> Result<u64, postgres::Error>;
+    fn insert_user(&mut self, user: &User) -> Result<u64, postgres::Error>;
+    fn insert_experimenter(&mut self, experimenter: &Experimenter) -> Result<u64, postgres::Error>;
+    fn insert_proposal(&mut self, proposal: &Proposal) -> Result<i32, postgres::Error>;
+    fn insert_dataset(&mut self, dataset: &Dataset) -> Result<i32, postgres::Error>;
+    fn insert_analyzed_file(&mut self, analyzed_file: &AnalyzedFile) -> Result<u64, postgres::Error>;
+    fn insert_dataset_image(&mut self, image: &DatasetImage) -> Result<u64, postgres::Error>;
+}
+
+impl DbWriter for Client
+{
+    fn begin(&mut self) -> Result<(), postgres::Error>
+    {
+        self.batch_execute("BEGIN")
+    }
+    fn commit(&mut self) -> Result<(), postgres::Error>
+    {
+        self.batch_execute("COMMIT")
+    }
+    fn rollback(&mut self) -> Result<(), postgres::Error>
+    {
+        self.batch_execute("ROLLBACK")
+    }
+    fn get_datasets_by_path(&mut self, path: &str, checksum: Option<&str>) -> Result<Vec<Dataset>, postgres::Error>
+    {
+        get_datasets_by_path(self, path, checksum)
+    }
+    fn update_dataset(&mut self, dataset_id: i32, dataset: &Dataset) -> Result<u64, postgres::Error>
+    {
+        update_dataset(self, dataset_id, dataset)
+    }
+    fn insert_user(&mut self, user: &User) -> Result<u64, postgres::Error>
+    {
+        insert_user(self, user)
+    }
+    fn insert_experimenter(&mut self, experimenter: &Experimenter) -> Result<u64, postgres::Error>
+    {
+        insert_experimenter(self, experimenter)
+    }
+    fn insert_proposal(&mut self, proposal: &Proposal) -> Result<i32, postgres::Error>
+    {
+        insert_proposal(self, proposal)
+    }
+    fn insert_dataset(&mut self, dataset: &Dataset) -> Result<i32, postgres::Error>
+    {
+        insert_dataset(self, dataset)
+    }
+    fn insert_analyzed_file(&mut self, analyzed_file: &AnalyzedFile) -> Result<u64, postgres::Error>
+    {
+        insert_analyzed_file(self, analyzed_file)
+    }
+    fn insert_dataset_image(&mut self, image: &DatasetImage) -> Result<u64, postgres::Error>
+    {
+        insert_dataset_image(self, image)
+    }
+}
+
 pub fn insert_user(db_client: &mut Client, user: &User) -> Result<u64, postgres::Error> 
 {
     let query = "INSERT INTO users (badge, username, first_name, last_name, institution, email, user_access_control_id) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING";
@@ -353,8 +645,8 @@ pub fn insert_proposal(db_client: &mut Client, proposal: &Proposal) -> Result<i3
 
 pub fn insert_dataset(db_client: &mut Client, dataset: &Dataset) -> Result<i32, postgres::Error> 
 {
-    let query = "INSERT INTO datasets (path, acquisition_timestamp, beamline_id, syncotron_run_id, scan_type_id) VALUES ($1, $2, $3, $4, $5) RETURNING id";
-    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&dataset.path, &dataset.acquisition_timestamp, &dataset.beamline_id, &dataset.syncotron_run_id, &dataset.scan_type_id];
+    let query = "INSERT INTO datasets (path, acquisition_timestamp, beamline_id, syncotron_run_id, scan_type_id, scan_rank, scan_dimensions, scan_points, checksum) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id";
+    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&dataset.path, &dataset.acquisition_timestamp, &dataset.beamline_id, &dataset.syncotron_run_id, &dataset.scan_type_id, &dataset.scan_rank, &dataset.scan_dimensions, &dataset.scan_points, &dataset.checksum];
     for row in  db_client.query(query, params)?
     {
         let id:i32 = row.get(0);
@@ -362,3 +654,36 @@ pub fn insert_dataset(db_client: &mut Client, dataset: &Dataset) -> Result<i32,
     }
     Ok(-1)
 }
+
+pub fn insert_analyzed_file(db_client: &mut Client, analyzed_file: &AnalyzedFile) -> Result<u64, postgres::Error> 
+{
+    let query = "INSERT INTO analyzed_files (dataset_id, path, detector, processed_timestamp) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING";
+    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&analyzed_file.dataset_id, &analyzed_file.path, &analyzed_file.detector, &analyzed_file.processed_timestamp];
+    return db_client.execute(query, params)
+}
+
+pub fn insert_dataset_image(db_client: &mut Client, image: &DatasetImage) -> Result<u64, postgres::Error> 
+{
+    let query = "INSERT INTO dataset_images (dataset_id, analyzed_path, element, path) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING";
+    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&image.dataset_id, &image.analyzed_path, &image.element, &image.path];
+    return db_client.execute(query, params)
+}
+
+// shifts can change during the run so update the numbers of requests already stored
+pub fn insert_beamtime_request(db_client: &mut Client, request: &BeamtimeRequest) -> Result<u64, postgres::Error> 
+{
+    let query = "INSERT INTO beamtime_requests (beamtime_id, proposal_id, syncotron_run_id, beamline_id, requested_shifts, granted_shifts, scheduled_shifts, total_scheduled_shifts, time_unit, status) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+                 ON CONFLICT (beamtime_id, beamline_id) DO UPDATE SET proposal_id = EXCLUDED.proposal_id, syncotron_run_id = EXCLUDED.syncotron_run_id, requested_shifts = EXCLUDED.requested_shifts, granted_shifts = EXCLUDED.granted_shifts, \
+                 scheduled_shifts = EXCLUDED.scheduled_shifts, total_scheduled_shifts = EXCLUDED.total_scheduled_shifts, time_unit = EXCLUDED.time_unit, status = EXCLUDED.status";
+    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&request.beamtime_id, &request.proposal_id, &request.syncotron_run_id, &request.beamline_id, &request.requested_shifts, &request.granted_shifts, &request.scheduled_shifts, &request.total_scheduled_shifts, &request.time_unit, &request.status];
+    return db_client.execute(query, params)
+}
+
+// ----------- Update Functions -----------------------------
+
+pub fn update_dataset(db_client: &mut Client, dataset_id: i32, dataset: &Dataset) -> Result<u64, postgres::Error> 
+{
+    let query = "UPDATE datasets SET path = $2, acquisition_timestamp = $3, beamline_id = $4, syncotron_run_id = $5, scan_type_id = $6, scan_rank = $7, scan_dimensions = $8, scan_points = $9, checksum = COALESCE($10, checksum) WHERE id = $1";
+    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&dataset_id, &dataset.path, &dataset.acquisition_timestamp, &dataset.beamline_id, &dataset.syncotron_run_id, &dataset.scan_type_id, &dataset.scan_rank, &dataset.scan_dimensions, &dataset.scan_points, &dataset.checksum];
+    return db_client.execute(query, params)
+}
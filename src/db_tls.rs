@@ -0,0 +1,391 @@
+use std::error::Error;
+use std::fs;
+use native_tls::{Certificate, Identity, TlsConnector};
+use postgres::{Client, NoTls};
+use postgres::config::SslMode;
+use postgres_native_tls::MakeTlsConnector;
+use percent_encoding::percent_decode_str;
+
+// TLS for the database connection with libpq sslmode semantics:
+// disable: never, prefer: if the server supports it, require: always without checking the certificate
+// (like verify-ca if a root certificate is given), verify-ca: check the certificate chain,
+// verify-full: check the chain and the host name.
+// Without sslmode the connection is not encrypted, like before TLS was supported (libpq defaults to prefer).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsMode
+{
+    Disable,
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsOptions
+{
+    pub mode: TlsMode,
+    // CA bundle in PEM format
+    pub root_cert: Option<String>,
+    // client certificate and its PKCS#8 key in PEM format
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+impl Default for TlsOptions
+{
+    fn default() -> Self
+    {
+        TlsOptions { mode: TlsMode::Disable, root_cert: None, client_cert: None, client_key: None }
+    }
+}
+
+// the postgres crate only knows disable, prefer and require so these are taken out of the connection string
+static TLS_KEYS: &[&str] = &["sslmode", "sslrootcert", "sslcert", "sslkey"];
+
+pub fn parse_tls_mode(mode: &str) -> Result<TlsMode, String>
+{
+    match mode
+    {
+        "disable" => Ok(TlsMode::Disable),
+        "allow" | "prefer" => Ok(TlsMode::Prefer),
+        "require" => Ok(TlsMode::Require),
+        "verify-ca" => Ok(TlsMode::VerifyCa),
+        "verify-full" => Ok(TlsMode::VerifyFull),
+        _ => Err(format!("Error: unknown sslmode {}", mode)),
+    }
+}
+
+impl TlsOptions
+{
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String>
+    {
+        match key
+        {
+            "sslmode" => self.mode = parse_tls_mode(value)?,
+            "sslrootcert" => self.root_cert = Some(value.to_string()),
+            "sslcert" => self.client_cert = Some(value.to_string()),
+            "sslkey" => self.client_key = Some(value.to_string()),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn build_connector(&self) -> Result<MakeTlsConnector, String>
+    {
+        Ok(MakeTlsConnector::new(self.build_tls_connector()?))
+    }
+
+    fn build_tls_connector(&self) -> Result<TlsConnector, String>
+    {
+        let mut builder = TlsConnector::builder();
+        if let Some(root_cert) = self.root_cert.as_ref()
+        {
+            let pem = fs::read(root_cert).map_err(|e| format!("Error reading sslrootcert {}: {:?}", root_cert, e))?;
+            let certs = Certificate::stack_from_pem(&pem).map_err(|e| format!("Error parsing sslrootcert {}: {}", root_cert, e))?;
+            for cert in certs
+            {
+                builder.add_root_certificate(cert);
+            }
+        }
+        match (self.client_cert.as_ref(), self.client_key.as_ref())
+        {
+            (Some(cert_path), Some(key_path)) =>
+            {
+                let cert = fs::read(cert_path).map_err(|e| format!("Error reading sslcert {}: {:?}", cert_path, e))?;
+                let key = fs::read(key_path).map_err(|e| format!("Error reading sslkey {}: {:?}", key_path, e))?;
+                let identity = Identity::from_pkcs8(&cert, &key).map_err(|e| format!("Error loading client certificate {}: {}", cert_path, e))?;
+                builder.identity(identity);
+            }
+            (None, None) => (),
+            _ => return Err(String::from("Error: sslcert and sslkey must be given together")),
+        }
+        let verify_chain = match self.mode
+        {
+            TlsMode::VerifyCa | TlsMode::VerifyFull => true,
+            TlsMode::Require => self.root_cert.is_some(),
+            _ => false,
+        };
+        builder.danger_accept_invalid_certs(!verify_chain);
+        builder.danger_accept_invalid_hostnames(self.mode != TlsMode::VerifyFull);
+        builder.build().map_err(|e| format!("Error creating tls connector: {}", e))
+    }
+}
+
+fn split_key_value(param: &str) -> Option<(&str, &str)>
+{
+    let (key, value) = param.split_once('=')?;
+    Some((key.trim(), value.trim()))
+}
+
+// key=value strings are separated by whitespace, values can be quoted as 'a b' with \' and \\ escapes
+fn split_params(conn_str: &str) -> Vec<&str>
+{
+    let mut params = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in conn_str.char_indices()
+    {
+        if escaped
+        {
+            escaped = false;
+            continue;
+        }
+        match c
+        {
+            '\\' if quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted =>
+            {
+                if let Some(s) = start.take()
+                {
+                    params.push(&conn_str[s..i]);
+                }
+                continue;
+            }
+            _ => (),
+        }
+        if start.is_none()
+        {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start
+    {
+        params.push(&conn_str[s..]);
+    }
+    params
+}
+
+fn unquote(value: &str) -> String
+{
+    let inner = match value.strip_prefix('\'')
+    {
+        Some(inner) => inner.strip_suffix('\'').unwrap_or(inner),
+        None => return value.to_string(),
+    };
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next()
+    {
+        match c
+        {
+            '\\' => unquoted.push(chars.next().unwrap_or('\\')),
+            _ => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+// Removes the ssl options from a url (?sslmode=require&...) or key=value connection string
+pub fn split_tls_options(conn_str: &str) -> Result<(String, TlsOptions), String>
+{
+    let mut options = TlsOptions::default();
+    if conn_str.contains("://")
+    {
+        let (base, query) = match conn_str.split_once('?')
+        {
+            Some((base, query)) => (base, query),
+            None => return Ok((conn_str.to_string(), options)),
+        };
+        let mut kept = Vec::new();
+        for param in query.split('&')
+        {
+            match split_key_value(param)
+            {
+                Some((key, value)) if TLS_KEYS.contains(&key) =>
+                {
+                    let value = percent_decode_str(value).decode_utf8().map_err(|e| format!("Error decoding {}: {}", key, e))?;
+                    options.set(key, &value)?
+                }
+                _ => kept.push(param),
+            }
+        }
+        let stripped = match kept.is_empty()
+        {
+            true => base.to_string(),
+            false => format!("{}?{}", base, kept.join("&")),
+        };
+        return Ok((stripped, options));
+    }
+    let mut kept = Vec::new();
+    for param in split_params(conn_str)
+    {
+        match split_key_value(param)
+        {
+            Some((key, value)) if TLS_KEYS.contains(&key) => options.set(key, &unquote(value))?,
+            _ => kept.push(param),
+        }
+    }
+    Ok((kept.join(" "), options))
+}
+
+pub fn connect(pg_config: &postgres::Config, options: &TlsOptions) -> Result<Client, String>
+{
+    let mut pg_config = pg_config.clone();
+    let result = match options.mode
+    {
+        TlsMode::Disable =>
+        {
+            pg_config.ssl_mode(SslMode::Disable);
+            pg_config.connect(NoTls)
+        }
+        TlsMode::Prefer =>
+        {
+            pg_config.ssl_mode(SslMode::Prefer);
+            pg_config.connect(options.build_connector()?)
+        }
+        _ =>
+        {
+            pg_config.ssl_mode(SslMode::Require);
+            pg_config.connect(options.build_connector()?)
+        }
+    };
+    result.map_err(|e| match e.source()
+    {
+        Some(source) => format!("{}: {}", e, source),
+        None => format!("{}", e),
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use native_tls::TlsAcceptor;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::{X509, X509NameBuilder};
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+
+    #[test]
+    fn url_tls_options()
+    {
+        let (stripped, options) = split_tls_options("postgresql://user@host/db?sslmode=verify-ca&sslrootcert=%2Fetc%2Fca.pem&application_name=x").unwrap();
+        assert_eq!(stripped, "postgresql://user@host/db?application_name=x");
+        assert_eq!(options.mode, TlsMode::VerifyCa);
+        assert_eq!(options.root_cert.as_deref(), Some("/etc/ca.pem"));
+        let (stripped, options) = split_tls_options("postgresql://user@host/db?sslmode=require").unwrap();
+        assert_eq!(stripped, "postgresql://user@host/db");
+        assert_eq!(options.mode, TlsMode::Require);
+        let (_, options) = split_tls_options("postgresql://user@host/db?sslrootcert=C%3A%2Fmy%20certs%2Fca.pem").unwrap();
+        assert_eq!(options.root_cert.as_deref(), Some("C:/my certs/ca.pem"));
+        assert!(split_tls_options("postgresql://user@host/db?sslrootcert=%FF").is_err());
+    }
+
+    #[test]
+    fn key_value_tls_options()
+    {
+        let (stripped, options) = split_tls_options("host=db user=me sslmode=verify-full sslrootcert='/path with space/ca.pem' dbname=micdb").unwrap();
+        assert_eq!(stripped, "host=db user=me dbname=micdb");
+        assert_eq!(options.mode, TlsMode::VerifyFull);
+        assert_eq!(options.root_cert.as_deref(), Some("/path with space/ca.pem"));
+        assert!(split_tls_options("host=db sslmode=sometimes").is_err());
+    }
+
+    #[test]
+    fn quoted_values_are_kept_whole()
+    {
+        let (stripped, options) = split_tls_options("host=db password='a b' sslcert='it\\'s.pem' sslkey=key.pem").unwrap();
+        assert_eq!(stripped, "host=db password='a b'");
+        assert_eq!(options.client_cert.as_deref(), Some("it's.pem"));
+        assert_eq!(options.client_key.as_deref(), Some("key.pem"));
+        assert_eq!(options.mode, TlsMode::Disable);
+    }
+
+    fn make_cert(name: &str, issuer: Option<(&X509, &PKey<Private>)>) -> (X509, PKey<Private>)
+    {
+        let key = PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        match issuer
+        {
+            Some((ca_cert, ca_key)) =>
+            {
+                builder.set_issuer_name(ca_cert.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new().dns(name).build(&builder.x509v3_context(Some(ca_cert), None)).unwrap();
+                builder.append_extension(san).unwrap();
+                builder.sign(ca_key, MessageDigest::sha256()).unwrap();
+            }
+            None =>
+            {
+                builder.set_issuer_name(&subject).unwrap();
+                builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+                builder.sign(&key, MessageDigest::sha256()).unwrap();
+            }
+        }
+        (builder.build(), key)
+    }
+
+    // handshake with a server presenting a localhost certificate signed by the test CA
+    fn handshake(options: &TlsOptions, addr: std::net::SocketAddr, host: &str) -> bool
+    {
+        let connector = options.build_tls_connector().unwrap();
+        let stream = TcpStream::connect(addr).unwrap();
+        connector.connect(host, stream).is_ok()
+    }
+
+    #[test]
+    fn verify_modes_against_generated_ca()
+    {
+        let (ca_cert, ca_key) = make_cert("mic_db_fill test CA", None);
+        let (server_cert, server_key) = make_cert("localhost", Some((&ca_cert, &ca_key)));
+        let (other_ca, _) = make_cert("other CA", None);
+        let ca_path = std::env::temp_dir().join(format!("mic_db_fill_{}_ca.pem", std::process::id()));
+        let other_ca_path = std::env::temp_dir().join(format!("mic_db_fill_{}_other_ca.pem", std::process::id()));
+        fs::write(&ca_path, ca_cert.to_pem().unwrap()).unwrap();
+        fs::write(&other_ca_path, other_ca.to_pem().unwrap()).unwrap();
+
+        let identity = Identity::from_pkcs8(&server_cert.to_pem().unwrap(), &server_key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move ||
+        {
+            for stream in listener.incoming().flatten()
+            {
+                let _ = acceptor.accept(stream);
+            }
+        });
+
+        let options = |mode: TlsMode, root_cert: &std::path::Path| TlsOptions
+        {
+            mode: mode,
+            root_cert: Some(root_cert.to_string_lossy().to_string()),
+            client_cert: None,
+            client_key: None,
+        };
+        assert!(handshake(&options(TlsMode::VerifyFull, &ca_path), addr, "localhost"));
+        assert!(!handshake(&options(TlsMode::VerifyFull, &ca_path), addr, "otherhost"));
+        assert!(handshake(&options(TlsMode::VerifyCa, &ca_path), addr, "otherhost"));
+        assert!(!handshake(&options(TlsMode::VerifyCa, &other_ca_path), addr, "localhost"));
+        // require checks the chain only when a root certificate is given
+        assert!(!handshake(&options(TlsMode::Require, &other_ca_path), addr, "localhost"));
+        assert!(handshake(&TlsOptions { mode: TlsMode::Require, ..TlsOptions::default() }, addr, "otherhost"));
+
+        let mut missing_key = options(TlsMode::VerifyFull, &ca_path);
+        missing_key.client_cert = Some(ca_path.to_string_lossy().to_string());
+        assert!(missing_key.build_tls_connector().is_err());
+        let missing_ca = options(TlsMode::VerifyFull, std::path::Path::new("/nonexistent/ca.pem"));
+        assert!(missing_ca.build_tls_connector().is_err());
+
+        fs::remove_file(&ca_path).unwrap();
+        fs::remove_file(&other_ca_path).unwrap();
+    }
+}
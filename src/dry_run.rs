@@ -0,0 +1,218 @@
+use std::fs::File;
+use std::io;
+use std::collections::HashSet;
+use serde::Serialize;
+use postgres::Client;
+use crate::database::{self, DbWriter};
+
+// Recorded datasets get negative ids so they can't be mistaken for rows in the database,
+// -1 is left out since callers take it as a failed insert
+const FIRST_PLACEHOLDER_ID: i32 = -2;
+
+// Collects everything an ingestion would insert without touching the database.
+// The database is only read to look up datasets, users and proposals that already exist.
+#[derive(Serialize, Default)]
+pub struct DryRunRecorder<'a>
+{
+    users: Vec<database::User>,
+    proposals: Vec<database::Proposal>,
+    datasets: Vec<database::Dataset>,
+    updated_datasets: Vec<database::Dataset>,
+    experimenters: Vec<database::Experimenter>,
+    analyzed_files: Vec<database::AnalyzedFile>,
+    dataset_images: Vec<database::DatasetImage>,
+    #[serde(skip)]
+    user_badges: HashSet<i32>,
+    #[serde(skip)]
+    proposal_ids: HashSet<i32>,
+    #[serde(skip)]
+    next_dataset_id: i32,
+    // record counts when the current transaction started
+    #[serde(skip)]
+    savepoint: Option<[usize; 7]>,
+    #[serde(skip)]
+    db_reader: Option<&'a mut Client>,
+}
+
+impl<'a> DryRunRecorder<'a>
+{
+    pub fn new(db_reader: Option<&'a mut Client>) -> Self
+    {
+        DryRunRecorder { next_dataset_id: FIRST_PLACEHOLDER_ID, db_reader: db_reader, ..Default::default() }
+    }
+
+    pub fn print(&self)
+    {
+        println!("Dry run: {} users, {} proposals, {} datasets, {} experimenter links, {} analyzed files would be inserted, {} datasets updated", self.users.len(), self.proposals.len(), self.datasets.len(), self.experimenters.len(), self.analyzed_files.len(), self.updated_datasets.len());
+        print_records("user", &self.users);
+        print_records("proposal", &self.proposals);
+        print_records("dataset", &self.datasets);
+        print_records("experimenter", &self.experimenters);
+        print_records("analyzed file", &self.analyzed_files);
+        print_records("dataset image", &self.dataset_images);
+        print_updates(&self.updated_datasets);
+    }
+
+    pub fn save_json(&self, file_path: &str) -> Result<(), io::Error>
+    {
+        let file = File::create(file_path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+fn print_records<T: Serialize>(label: &str, records: &Vec<T>)
+{
+    for record in records.iter()
+    {
+        println!("would insert {}: {}", label, serde_json::to_string(record).unwrap_or_default());
+    }
+}
+
+fn print_updates(records: &Vec<database::Dataset>)
+{
+    for record in records.iter()
+    {
+        println!("would update dataset {}: {}", record.get_id(), serde_json::to_string(record).unwrap_or_default());
+    }
+}
+
+impl<'a> DbWriter for DryRunRecorder<'a>
+{
+    fn begin(&mut self) -> Result<(), postgres::Error>
+    {
+        self.savepoint = Some([self.users.len(), self.proposals.len(), self.datasets.len(), self.experimenters.len(), self.analyzed_files.len(), self.dataset_images.len(), self.updated_datasets.len()]);
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), postgres::Error>
+    {
+        self.savepoint = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), postgres::Error>
+    {
+        if let Some(counts) = self.savepoint.take()
+        {
+            self.users.truncate(counts[0]);
+            self.proposals.truncate(counts[1]);
+            self.datasets.truncate(counts[2]);
+            self.experimenters.truncate(counts[3]);
+            self.analyzed_files.truncate(counts[4]);
+            self.dataset_images.truncate(counts[5]);
+            self.updated_datasets.truncate(counts[6]);
+            self.user_badges = self.users.iter().map(|u| u.badge).collect();
+            self.proposal_ids = self.proposals.iter().map(|p| p.id).collect();
+        }
+        Ok(())
+    }
+
+    fn get_datasets_by_path(&mut self, path: &str, checksum: Option<&str>) -> Result<Vec<database::Dataset>, postgres::Error>
+    {
+        if let Some(recorded) = self.datasets.iter().find(|d| d.get_path() == path)
+        {
+            return Ok(vec![recorded.clone()]);
+        }
+        match self.db_reader.as_mut()
+        {
+            Some(db_client) => database::get_datasets_by_path(db_client, path, checksum),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn update_dataset(&mut self, dataset_id: i32, dataset: &database::Dataset) -> Result<u64, postgres::Error>
+    {
+        let mut recorded = dataset.clone();
+        recorded.set_id(dataset_id);
+        self.updated_datasets.push(recorded);
+        Ok(1)
+    }
+
+    // users and proposals are inserted with ON CONFLICT DO NOTHING so only keep the first one
+    // and skip the ones already in the database
+    fn insert_user(&mut self, user: &database::User) -> Result<u64, postgres::Error>
+    {
+        if !self.user_badges.insert(user.badge)
+        {
+            return Ok(0);
+        }
+        if let Some(db_client) = self.db_reader.as_mut()
+        {
+            if database::user_exists(db_client, user.badge)?
+            {
+                return Ok(0);
+            }
+        }
+        self.users.push(user.clone());
+        Ok(1)
+    }
+
+    fn insert_experimenter(&mut self, experimenter: &database::Experimenter) -> Result<u64, postgres::Error>
+    {
+        self.experimenters.push(*experimenter);
+        Ok(1)
+    }
+
+    fn insert_proposal(&mut self, proposal: &database::Proposal) -> Result<i32, postgres::Error>
+    {
+        if !self.proposal_ids.insert(proposal.id)
+        {
+            return Ok(proposal.id);
+        }
+        if let Some(db_client) = self.db_reader.as_mut()
+        {
+            if database::proposal_exists(db_client, proposal.id)?
+            {
+                return Ok(proposal.id);
+            }
+        }
+        self.proposals.push(proposal.clone());
+        Ok(proposal.id)
+    }
+
+    // ids are only placeholders to link the recorded experimenters and analyzed files
+    fn insert_dataset(&mut self, dataset: &database::Dataset) -> Result<i32, postgres::Error>
+    {
+        let id = self.next_dataset_id;
+        self.next_dataset_id -= 1;
+        let mut recorded = dataset.clone();
+        recorded.set_id(id);
+        self.datasets.push(recorded);
+        Ok(id)
+    }
+
+    fn insert_analyzed_file(&mut self, analyzed_file: &database::AnalyzedFile) -> Result<u64, postgres::Error>
+    {
+        self.analyzed_files.push(analyzed_file.clone());
+        Ok(1)
+    }
+
+    fn insert_dataset_image(&mut self, image: &database::DatasetImage) -> Result<u64, postgres::Error>
+    {
+        self.dataset_images.push(image.clone());
+        Ok(1)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn recorded_datasets_get_placeholder_ids()
+    {
+        let mut recorder = DryRunRecorder::new(None);
+        let first = database::Dataset::new(1, 1, 1, "/data1/2idd/2025-1/Smith/mda/2idd_0001.mda", std::time::SystemTime::now());
+        let second = database::Dataset::new(1, 1, 1, "/data1/2idd/2025-1/Smith/mda/2idd_0002.mda", std::time::SystemTime::now());
+        assert_eq!(recorder.insert_dataset(&first).unwrap(), -2);
+        recorder.begin().unwrap();
+        assert_eq!(recorder.insert_dataset(&second).unwrap(), -3);
+        let found = recorder.get_datasets_by_path(second.get_path(), None).unwrap();
+        assert_eq!(found.iter().map(|d| d.get_id()).collect::<Vec<i32>>(), vec![-3]);
+        recorder.rollback().unwrap();
+        assert!(recorder.get_datasets_by_path(second.get_path(), None).unwrap().is_empty());
+        assert_eq!(recorder.get_datasets_by_path(first.get_path(), None).unwrap()[0].get_id(), -2);
+    }
+}
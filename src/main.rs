@@ -1,12 +1,9 @@
-use std::env;
-use std::fs::File;
 use std::path::Path;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
-use std::io::{self, Read};
-use reqwest;
-//use reqwest::blocking::Client;
-use clap::Parser;
-use postgres::{Client, NoTls};
+use clap::{Parser, Subcommand, ValueEnum};
+use postgres::Client;
 
 //use tokio;
 
@@ -15,20 +12,135 @@ mod data_walker;
 mod activity;
 mod beamtime;
 mod synco_runs;
+mod dry_run;
+mod migrate;
+mod batch;
+mod path_rules;
+mod schedule_cache;
+mod schedule_source;
+mod mock_server;
+mod credentials;
+mod db_tls;
+mod name_match;
+mod overrides;
+mod resolve;
+mod report;
 
 use activity::{Activity, Experimenter};
 
-static STR_URL_ACTIVITY_HEADER: &'static str = "https://beam-api.aps.anl.gov/beamline-scheduling//sched-api/activity/findByRunNameAndBeamlineId/";
-//static STR_URL_BEAMTIME_HEADER: &'static str = "https://beam-api.aps.anl.gov/beamline-scheduling/sched-api/beamtimeRequests/findBeamtimeRequestsByRunAndBeamline/";
 static STR_IMG_DAT: &'static str = "img.dat";
 static STR_MDA: &'static str = "mda";
 static STR_PI: &'static str = "Principal Investigator";
 static STR_CI: &'static str = "Co-Investigator";
+static STR_STAFF_DATA: &'static str = "Staff Data";
+static STR_LOCAL_CONTACT: &'static str = "Local Contact";
+static STR_STEP_SCAN: &'static str = "Step Scan";
+static STR_FLY_SCAN: &'static str = "Fly Scan";
 static NUM_DETECTORS: u32 = 8;
+static NUM_PI_CANDIDATES_SHOWN: usize = 3;
+static NUM_RESOLVE_CANDIDATES: usize = 5;
+// proposals named in a message about a file outside or inside several activities
+static NUM_PROPOSALS_LISTED: usize = 5;
+
+// What to do with datasets whose path is already in the database
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum ExistingMode
+{
+    /// Leave the existing row untouched and add missing links
+    Skip,
+    /// Update timestamp, scan type and scan info of the existing row and add missing links
+    Update,
+    /// Print what differs from the existing row without changing it
+    Report,
+}
+
+// What the datasets of a directory belong to
+enum Attribution<'a>
+{
+    // activities and whether they were picked by acquisition time
+    Activities(Vec<&'a Activity>, bool),
+    Staff(Vec<&'a database::User>),
+    // (reason, detail) for the unmatched data report
+    Unmatched(report::Reason, String),
+    NoMatch,
+}
+
+// How datasets in a directory are attributed to scheduled activities
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum AttributionMode
+{
+    /// Match the PI directory name to the PIs of the schedule
+    Name,
+    /// Pick the activity whose start and end time contain each file's acquisition time
+    Time,
+    /// Match by name, use acquisition time for directories that match no PI
+    NameThenTime,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands
+{
+    /// Create or upgrade the database schema
+    Migrate
+    {
+        /// Create the database named in SVC_PSQL_CONN_STR if it does not exist
+        #[arg(long, action)]
+        create_db: bool,
+        /// Mark a database created before migrations were shipped as having the initial schema, then upgrade it
+        #[arg(long, action)]
+        baseline: bool,
+    },
+    /// Add new synchrotron runs and update changed ones from the scheduling API
+    SyncRuns
+    {
+        /// Load the run list from a file instead of the scheduling API
+        #[arg(short, long)]
+        filename: Option<String>,
+    },
+    /// Store requested, granted and scheduled shifts of every proposal for a run and beamline
+    BeamtimeRequests
+    {
+        /// beamline name
+        #[arg(short, long)]
+        beamline: String,
+
+        /// beamtime run
+        #[arg(short, long)]
+        run: String,
+
+        /// Load the beamtime requests from a file instead of the scheduling API
+        #[arg(short, long)]
+        filename: Option<String>,
+    },
+    /// Ingest every beamline and run listed in a toml or yaml manifest
+    Batch
+    {
+        /// Manifest file (.toml, .yaml or .yml)
+        manifest: String,
+    },
+    /// Serve canned scheduling API responses for testing, use with --api-url
+    MockServer
+    {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8099")]
+        address: String,
+
+        /// Serve saved responses from this directory (cache layout) instead of the built in fixtures
+        #[arg(long)]
+        dir: Option<String>,
+
+        /// Answer the first requests with these http status codes, ex: 503,503 to test retries or 401 for a refused token
+        #[arg(long, value_delimiter = ',')]
+        fail_with: Vec<u16>,
+    },
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// How deep to search for datasets
     #[arg(short, long, default_value_t=2)]
     num_recursive: u32,
@@ -37,10 +149,22 @@ struct Args {
     #[arg(short, long, action)]
     export_counts_png: bool,
 
-    /// Number of times to greet
+    /// Directory to save exported png files in
+    #[arg(long, default_value = "counts_png")]
+    png_dir: String,
+
+    /// Clip this percent of the lowest and highest counts when scaling png files, default is min/max
+    #[arg(long, value_parser = parse_png_percentile)]
+    png_percentile: Option<f32>,
+
+    /// Dry run: search and match datasets but only print what would be inserted
     #[arg(short, long, action)]
     test: bool,
 
+    /// Save the records of a dry run (--test) to this json file
+    #[arg(long)]
+    dry_run_json: Option<String>,
+
     /// Verbose output
     #[arg(short, long, action)]
     verbose: bool,
@@ -65,6 +189,159 @@ struct Args {
     #[arg(short, long, action)]
     query_db_users: bool,
 
+    /// How to handle datasets that are already in the database
+    #[arg(long, value_enum, default_value_t = ExistingMode::Skip)]
+    existing: ExistingMode,
+
+    /// Store a sha256 checksum of each raw file and use it to find datasets that were moved, a copy next to its original is a new dataset
+    #[arg(long, action)]
+    checksum: bool,
+
+    /// Lowest confidence (0 to 1) to accept a fuzzy match between a directory and a PI name
+    #[arg(long, default_value_t = name_match::DEFAULT_THRESHOLD)]
+    name_match_threshold: f64,
+
+    /// How to find the activity that owns the datasets of a directory
+    #[arg(long, value_enum, default_value_t = AttributionMode::Name)]
+    attribution: AttributionMode,
+
+    /// Toml or csv file mapping directory paths or globs to a proposal, activity or badges, checked before name matching
+    #[arg(long)]
+    overrides: Option<String>,
+
+    /// Folder names (globs, comma separated) holding staff or commissioning data, ex: commissioning,test*
+    #[arg(long, value_delimiter = ',')]
+    staff_dirs: Vec<String>,
+
+    /// Badge of the staff user owning --staff-dirs folders and staff overrides without badges
+    #[arg(long)]
+    staff_badge: Option<i32>,
+
+    /// Ask what to do with each directory that matches no activity and save the answers to --overrides
+    #[arg(long, action, requires = "overrides")]
+    resolve: bool,
+
+    /// Save the directories that were not attributed to a proposal to <REPORT>.json and <REPORT>.md
+    #[arg(long, default_value = "unmatched_report")]
+    report: String,
+
+    /// Rules to detect beamline and run from --search-dir when they are not given, default is the /data1 layout
+    #[arg(long)]
+    path_rules: Option<String>,
+
+    /// Base url of the scheduling API
+    #[arg(long, default_value = schedule_source::STR_DEFAULT_API_URL)]
+    api_url: String,
+
+    /// Credentials file (toml, owner only permissions), default ~/.mic_db_fill.toml if it exists
+    #[arg(long)]
+    credentials: Option<String>,
+
+    /// Seconds to wait for a scheduling API response
+    #[arg(long, default_value_t = 30)]
+    http_timeout: u64,
+
+    /// Retries of scheduling API requests that failed with a server error or timed out
+    #[arg(long, default_value_t = 3)]
+    http_retries: u32,
+
+    /// Seconds to wait before the first retry, doubled for every following retry
+    #[arg(long, default_value_t = 1.0)]
+    http_backoff: f64,
+
+    /// Load schedules from saved responses in this directory (cache layout) instead of the scheduling API
+    #[arg(long, conflicts_with = "cache_dir")]
+    schedule_dir: Option<String>,
+
+    /// Cache scheduling API responses in this directory and reuse them on later runs
+    #[arg(long)]
+    cache_dir: Option<String>,
+
+    /// Only use responses from --cache-dir, never contact the scheduling API
+    #[arg(long, action, requires = "cache_dir", conflicts_with = "refresh")]
+    offline: bool,
+
+    /// Fetch every response from the scheduling API and replace it in --cache-dir
+    #[arg(long, action, requires = "cache_dir")]
+    refresh: bool,
+
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct DatasetCounts
+{
+    inserted: u32,
+    updated: u32,
+    existing: u32,
+}
+
+#[derive(Default)]
+struct IngestSummary
+{
+    // (directory and proposal, datasets)
+    committed: Vec<(String, DatasetCounts)>,
+    // (directory and proposal, reason)
+    rolled_back: Vec<(String, String)>,
+    // (raw file, reason) of files whose acquisition time is in none or several activities
+    ambiguous: Vec<(String, String)>,
+}
+
+impl IngestSummary
+{
+    fn totals(&self) -> String
+    {
+        let num_inserted: u32 = self.committed.iter().map(|(_, c)| c.inserted).sum();
+        let num_updated: u32 = self.committed.iter().map(|(_, c)| c.updated).sum();
+        let num_existing: u32 = self.committed.iter().map(|(_, c)| c.existing).sum();
+        format!("{} proposals committed with {} datasets inserted, {} updated, {} already existing. {} proposals rolled back, {} ambiguous files", self.committed.len(), num_inserted, num_updated, num_existing, self.rolled_back.len(), self.ambiguous.len())
+    }
+
+    fn print(&self)
+    {
+        println!("Summary: {}", self.totals());
+        for (label, reason) in self.rolled_back.iter()
+        {
+            println!("Rolled back {}: {}", label, reason);
+        }
+        for (file_name, reason) in self.ambiguous.iter()
+        {
+            println!("Ambiguous {}: {}", file_name, reason);
+        }
+    }
+}
+
+// Why a run could not be ingested, schedule errors are kept apart so a batch can stop on a refused token
+enum IngestError
+{
+    Schedule(schedule_source::ApiError),
+    Failed(String),
+}
+
+impl From<String> for IngestError
+{
+    fn from(message: String) -> Self
+    {
+        IngestError::Failed(message)
+    }
+}
+
+impl std::fmt::Display for IngestError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            IngestError::Schedule(e) => write!(f, "{}", e),
+            IngestError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+struct PiCandidate<'a>
+{
+    activity: &'a Activity,
+    experimenter: &'a Experimenter,
+    score: name_match::NameScore,
 }
 
 struct Config
@@ -78,16 +355,30 @@ struct Config
     db_scan_types: std::collections::HashMap<String, database::ScanType>,
     run_id: i32,
     beamline_id: i32,
+    png_export_dir: Option<String>,
+    png_scaling: data_walker::PngScaling,
+    summary: IngestSummary,
+    existing_mode: ExistingMode,
+    use_checksum: bool,
+    name_match_threshold: f64,
+    attribution_mode: AttributionMode,
+    overrides: Option<overrides::OverrideStore>,
+    resolve: bool,
+    staff_dir_patterns: Vec<glob::Pattern>,
+    staff_badge: Option<i32>,
+    report: report::UnmatchedReport,
+    report_path: Option<String>,
+    schedule_source: Option<Box<dyn schedule_source::ScheduleSource>>,
     pub verbose: bool,
 }
 
 impl Config
 {
-    fn new(beam_schedule: &str,  verbose: bool) -> Self
+    fn new(verbose: bool) -> Self
     {
         Config 
         { 
-            activities: serde_json::from_str(&beam_schedule).unwrap(),
+            activities: Vec::new(),
             db_staff: Vec::new(),
             db_access_control: HashMap::new(),
             db_sync_runs: HashMap::new(),
@@ -96,31 +387,265 @@ impl Config
             db_scan_types: HashMap::new(),
             run_id: -1,
             beamline_id: -1,
+            png_export_dir: None,
+            png_scaling: data_walker::PngScaling::MinMax,
+            summary: IngestSummary::default(),
+            existing_mode: ExistingMode::Skip,
+            use_checksum: false,
+            name_match_threshold: name_match::DEFAULT_THRESHOLD,
+            attribution_mode: AttributionMode::Name,
+            overrides: None,
+            resolve: false,
+            staff_dir_patterns: Vec::new(),
+            staff_badge: None,
+            report: report::UnmatchedReport::default(),
+            report_path: None,
+            schedule_source: None,
             verbose: verbose 
         }
     }
-    fn search_for_pi_activity(&self, experimenter_lastname: &str) -> (Option<&Activity>, Option<&Experimenter>)
+    fn load_db_tables(&mut self, db_client: &mut Client) -> Result<(), String>
+    {
+        let mut load = || -> Result<(), postgres::Error>
+        {
+            database::get_all_staff_users(db_client, &mut self.db_staff)?;
+            database::get_access_control(db_client, &mut self.db_access_control)?;
+            database::get_sync_runs(db_client, &mut self.db_sync_runs)?;
+            database::get_experimenter_roles(db_client, &mut self.db_experimenter_roles)?;
+            database::get_scan_types(db_client, &mut self.db_scan_types)?;
+            database::get_beamlines(db_client, &mut self.db_beamlines)?;
+            Ok(())
+        };
+        load().map_err(|e| match e.as_db_error()
+        {
+            Some(db_error) => format!("Error loading database tables: {}", db_error),
+            None => format!("Error loading database tables: {}", e),
+        })
+    }
+
+    // PIs of all activities ranked by how well their last name matches the directory name
+    fn rank_pi_candidates(&self, dir_name: &str) -> Vec<PiCandidate<'_>>
+    {
+        let mut candidates = Vec::new();
+        for activity in self.activities.iter()
+        {
+            for experimenter in activity.beamtime.proposal.experimenters.iter()
+            {
+                if experimenter.piFlag.as_deref() != Some("Y")
+                {
+                    continue;
+                }
+                let score = name_match::score_name(dir_name, &experimenter.lastName, &experimenter.firstName);
+                if score.score >= name_match::MIN_CANDIDATE_SCORE
+                {
+                    candidates.push(PiCandidate { activity: activity, experimenter: experimenter, score: score });
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.score.score.partial_cmp(&a.score.score).unwrap_or(std::cmp::Ordering::Equal));
+        candidates
+    }
+
+    // Activities named by the override for this directory, None if it has no override
+    fn find_override_activities(&self, dir_path: &str) -> Option<Vec<&Activity>>
+    {
+        let dir_override = self.overrides.as_ref()?.find(dir_path).filter(|o| !o.staff)?;
+        let activities: Vec<&Activity> = self.activities.iter().filter(|activity|
+        {
+            let proposal = &activity.beamtime.proposal;
+            match (dir_override.proposal, dir_override.activity)
+            {
+                (Some(gup_id), _) => proposal.gupId == Some(gup_id),
+                (_, Some(activity_id)) => activity.activityId == Some(activity_id),
+                _ => proposal.experimenters.iter().any(|e| e.badge.parse::<i64>().map(|b| dir_override.badges.contains(&b)).unwrap_or(false)),
+            }
+        }).collect();
+        if activities.is_empty()
+        {
+            println!("Error: override {} for {} matches no activity of this run and beamline", dir_override.describe(), dir_path);
+        }
+        else
+        {
+            println!("override {} for {}: {}", dir_override.path, dir_path, dir_override.describe());
+        }
+        Some(activities)
+    }
+
+    // Owners of a directory marked as staff data by its override or one of the --staff-dirs names,
+    // None if it is neither
+    fn get_staff_dir_owners(&self, dir_path: &str, dir_name: &str) -> Option<Result<Vec<&database::User>, String>>
+    {
+        let mut badges = match self.overrides.as_ref().and_then(|o| o.find(dir_path))
+        {
+            Some(dir_override) if dir_override.staff => dir_override.badges.clone(),
+            // an override to a proposal wins over the folder name
+            Some(_) => return None,
+            None =>
+            {
+                let options = glob::MatchOptions { case_sensitive: false, ..Default::default() };
+                if !self.staff_dir_patterns.iter().any(|p| p.matches_with(dir_name, options))
+                {
+                    return None;
+                }
+                Vec::new()
+            }
+        };
+        if badges.is_empty()
+        {
+            match self.staff_badge
+            {
+                Some(badge) => badges.push(badge as i64),
+                None => return Some(Err(format!("no staff user for {}, set --staff-badge or badges in its override", dir_path))),
+            }
+        }
+        let mut owners = Vec::new();
+        for badge in badges.iter()
+        {
+            match self.db_staff.iter().find(|user| user.badge as i64 == *badge)
+            {
+                Some(user) => owners.push(user),
+                None => return Some(Err(format!("badge {} of {} is not a staff user in the database", badge, dir_path))),
+            }
+        }
+        println!("{} is staff data of {}", dir_path, owners.iter().map(|u| format!("{} {}", u.first_name, u.last_name)).collect::<Vec<String>>().join(", "));
+        Some(Ok(owners))
+    }
+
+    // Staff member whose name matches the directory, stricter than PI matching since any folder can look like a name
+    fn search_for_staff(&self, dir_name: &str) -> Option<&database::User>
+    {
+        let mut best: Option<(&database::User, name_match::NameScore)> = None;
+        for user in self.db_staff.iter()
+        {
+            let score = name_match::score_name(dir_name, &user.last_name, &user.first_name);
+            if score.score >= name_match::STAFF_THRESHOLD && best.map(|(_, b)| score.score > b.score).unwrap_or(true)
+            {
+                best = Some((user, score));
+            }
+        }
+        let (user, score) = best?;
+        println!("matched {} to staff {} {} ({}, confidence {:.2})", dir_name, user.first_name, user.last_name, score.reason, score.score);
+        Some(user)
+    }
+
+    // Override, staff folder names, PI name, staff name and acquisition time, in that order
+    fn attribute_dir(&self, dir_path: &str, dir_name: &str) -> Attribution<'_>
+    {
+        match self.get_staff_dir_owners(dir_path, dir_name)
+        {
+            Some(Ok(owners)) => return Attribution::Staff(owners),
+            Some(Err(e)) => return Attribution::Unmatched(report::Reason::StaffWithoutOwner, e),
+            None => (),
+        }
+        if let Some(activities) = self.find_override_activities(dir_path)
+        {
+            return match activities.is_empty()
+            {
+                true => Attribution::Unmatched(report::Reason::OverrideWithoutActivity, String::from("override matches no activity of this run and beamline")),
+                false => Attribution::Activities(activities, false),
+            };
+        }
+        if self.attribution_mode != AttributionMode::Time
+        {
+            match self.search_for_pi_activities(dir_name)
+            {
+                Ok(activities) if activities.len() > 0 => return Attribution::Activities(activities, false),
+                Ok(_) => (),
+                Err(e) => return Attribution::Unmatched(report::Reason::AmbiguousPi, e),
+            }
+        }
+        if let Some(user) = self.search_for_staff(dir_name)
+        {
+            return Attribution::Staff(vec![user]);
+        }
+        if self.attribution_mode != AttributionMode::Name && self.activities.len() > 0
+        {
+            println!("attributing {} by acquisition time", dir_path);
+            return Attribution::Activities(self.activities.iter().collect(), true);
+        }
+        Attribution::NoMatch
+    }
+
+    // Nearest PIs by name for the unmatched data report
+    fn get_report_candidates(&self, dir_name: &str) -> Vec<report::Candidate>
+    {
+        self.rank_pi_candidates(dir_name).iter().take(NUM_PI_CANDIDATES_SHOWN).map(|c| report::Candidate
+        {
+            name: format!("{} {}", c.experimenter.firstName, c.experimenter.lastName),
+            proposal: c.activity.beamtime.proposal.gupId,
+            activity: c.activity.activityId,
+            score: c.score.score,
+            reason: c.score.reason.to_string(),
+        }).collect()
+    }
+
+    fn save_report(&self)
+    {
+        if let Some(report_path) = self.report_path.as_ref()
+        {
+            if let Err(e) = self.report.save(report_path)
+            {
+                println!("{}", e);
+            }
+        }
+    }
+
+    // Best name matches and the activities scheduled while the files were acquired
+    fn get_resolve_candidates(&self, dir_name: &str, acquired_range: Option<(DateTime<Utc>, DateTime<Utc>)>) -> Vec<(&Activity, String)>
+    {
+        let mut candidates: Vec<(&Activity, String)> = Vec::new();
+        for candidate in self.rank_pi_candidates(dir_name).iter().take(NUM_PI_CANDIDATES_SHOWN)
+        {
+            candidates.push((candidate.activity, format!("{}, confidence {:.2}", candidate.score.reason, candidate.score.score)));
+        }
+        for activity in self.activities.iter()
+        {
+            let overlaps = match (get_activity_window(activity), acquired_range)
+            {
+                (Some((start, end)), Some((first_acquired, last_acquired))) => start <= last_acquired && first_acquired <= end,
+                _ => false,
+            };
+            if overlaps && !candidates.iter().any(|(a, _)| std::ptr::eq(*a, activity))
+            {
+                candidates.push((activity, String::from("scheduled during acquisition")));
+            }
+        }
+        candidates.truncate(NUM_RESOLVE_CANDIDATES);
+        candidates
+    }
+
+    // Activities of the best matching PI, more than one if the PI has several beamtimes in the run.
+    // Different PIs with the same best score are an error, their beamtimes can not be told apart by name.
+    fn search_for_pi_activities(&self, dir_name: &str) -> Result<Vec<&Activity>, String>
     {
-        let mut found_act = None;
-        let mut found_exp = None;
-        self.activities.iter().for_each(|activity| 
+        let candidates = self.rank_pi_candidates(dir_name);
+        match candidates.first()
         {
-            activity.beamtime.proposal.experimenters.iter().for_each(|experimenter: &Experimenter| 
+            Some(best) if best.score.score >= self.name_match_threshold => 
             {
-                let f_last_name:String = experimenter.lastName.chars().filter(|&c| c != '\'').collect();
-                // remove ' from last names since path is saved without it
-                if experimenter.piFlag.is_some() && ( experimenter.lastName == experimenter_lastname || f_last_name == experimenter_lastname)
+                let tied: Vec<&PiCandidate> = candidates.iter().filter(|c| c.score.score >= best.score.score).collect();
+                if let Some(other) = tied.iter().find(|c| c.experimenter.badge != best.experimenter.badge)
+                {
+                    return Err(format!("{} matches PIs {} {} (badge {}) and {} {} (badge {}) equally ({}, confidence {:.2})", dir_name, best.experimenter.firstName, best.experimenter.lastName, best.experimenter.badge, other.experimenter.firstName, other.experimenter.lastName, other.experimenter.badge, best.score.reason, best.score.score));
+                }
+                for candidate in tied.iter()
                 {
-                    if experimenter.piFlag.is_some() && experimenter.piFlag.as_ref().unwrap() == "Y"
+                    if candidate.score.score < 1.0 || self.verbose || tied.len() > 1
                     {
-                        //println!("found pi: {} {}", experimenter.firstName, experimenter.lastName);
-                        found_act = Some(activity);
-                        found_exp = Some(experimenter);
+                        println!("matched {} to PI {} {} proposal {:?} ({}, confidence {:.2})", dir_name, candidate.experimenter.firstName, candidate.experimenter.lastName, candidate.activity.beamtime.proposal.gupId, candidate.score.reason, candidate.score.score);
                     }
                 }
-            });
-        });
-        (found_act, found_exp)
+                Ok(tied.iter().map(|c| c.activity).collect())
+            }
+            _ => 
+            {
+                for candidate in candidates.iter().take(NUM_PI_CANDIDATES_SHOWN)
+                {
+                    println!("candidate for {}: {} {} proposal {:?} ({}, confidence {:.2})", dir_name, candidate.experimenter.firstName, candidate.experimenter.lastName, candidate.activity.beamtime.proposal.gupId, candidate.score.reason, candidate.score.score);
+                }
+                Ok(Vec::new())
+            }
+        }
     }
 
     fn get_bealine_id(&self) -> u32
@@ -142,8 +667,33 @@ impl Config
         }
     }
 
+    fn get_scan_type_id(&self, kind: data_walker::ScanKind) -> Option<i32>
+    {
+        let name = match kind
+        {
+            data_walker::ScanKind::Step => STR_STEP_SCAN,
+            data_walker::ScanKind::Fly => STR_FLY_SCAN,
+        };
+        if let Some(scan_type) = self.db_scan_types.get(name)
+        {
+            return Some(scan_type.get_id());
+        }
+        // fall back to a case insensitive match on the first word, ex: "step" or "Fly scan"
+        let short_name = name.split(' ').next().unwrap().to_lowercase();
+        for (key, val) in &self.db_scan_types
+        {
+            if key.to_lowercase().starts_with(&short_name)
+            {
+                return Some(val.get_id());
+            }
+        }
+        None
+    }
+
     fn init_run_info(&mut self, run_name: &str, beamline_name: &str)
     {
+        self.run_id = -1;
+        self.beamline_id = -1;
         if self.verbose
         {
             println!("searching run : {} len {}", run_name, run_name.len());
@@ -192,56 +742,85 @@ impl Config
     }
 }
 
-fn read_json_from_file(file_path: &str) -> Result<String, io::Error> 
+// Clipping 50 percent or more from both ends leaves nothing to scale
+fn parse_png_percentile(value: &str) -> Result<f32, String>
 {
-    // Open the file
-    let mut file = File::open(file_path)?;
+    let pct: f32 = value.parse().map_err(|e| format!("{}", e))?;
+    if pct > 0.0 && pct < 50.0
+    {
+        Ok(pct)
+    }
+    else
+    {
+        Err(format!("{} is not between 0 and 50", value))
+    }
+}
 
-    // Read the file contents into a string
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+fn get_schedule_cache(args: &Args) -> Option<schedule_cache::ScheduleCache>
+{
+    let mode = match (args.offline, args.refresh)
+    {
+        (true, _) => schedule_cache::CacheMode::Offline,
+        (_, true) => schedule_cache::CacheMode::Refresh,
+        _ => schedule_cache::CacheMode::Normal,
+    };
+    args.cache_dir.as_ref().map(|dir| schedule_cache::ScheduleCache::new(dir, mode, args.verbose))
+}
 
-    Ok(contents)
+// A single file takes precedence, then a directory of saved responses, then the api
+fn get_schedule_source(args: &Args, filename: Option<&String>, credentials: &credentials::Credentials) -> Result<Box<dyn schedule_source::ScheduleSource>, String>
+{
+    if let Some(filename) = filename
+    {
+        return Ok(Box::new(schedule_source::FileSource::new(filename)));
+    }
+    if let Some(dir) = args.schedule_dir.as_ref()
+    {
+        return Ok(Box::new(schedule_source::DirectorySource::new(dir)));
+    }
+    let options = schedule_source::HttpOptions
+    {
+        timeout: Duration::from_secs(args.http_timeout),
+        retries: args.http_retries,
+        backoff: Duration::from_secs_f64(args.http_backoff.max(0.0)),
+    };
+    match schedule_source::HttpSource::new(&args.api_url, get_schedule_cache(args), options, &credentials.auth_str)
+    {
+        Ok(source) => Ok(Box::new(source)),
+        Err(e) => Err(format!("Error creating http client: {:?}", e)),
+    }
 }
 
-fn read_json_from_url(url_path: &str) -> Result<String, reqwest::Error> 
+// Experimenters that can not be stored as users, they would fail the whole proposal
+fn check_experimenters(experimenters: &Vec<Experimenter>, config: &Config) -> Result<(), (report::Reason, String)>
 {
-    let auth_str = env::var("SVC_AUTH_STR").unwrap_or_else(|_| "Bearer ".to_string());
-    let client = reqwest::blocking::Client::new();
-    let resp = client.get(url_path)
-    .header("accept", "*/*")
-    .header("Authorization", auth_str)
-    .send()
-    .unwrap();
-    //let resp = reqwest::blocking::get(url_path)?;
-    if resp.status() != reqwest::StatusCode::OK
+    let uac = config.db_access_control.get("Visitor").unwrap();
+    for experimenter in experimenters.iter()
     {
-        panic!("Error: {}", resp.status());
+        match database::User::from_experimenter(experimenter, uac)
+        {
+            Ok(_) => (),
+            Err(database::UserError::NoEmail(detail)) => return Err((report::Reason::NoEmail, detail)),
+            Err(database::UserError::BadgeParse(detail)) => return Err((report::Reason::BadgeParseError, detail)),
+        }
     }
-    let body = resp.text().unwrap();
-    Ok(body,)
+    Ok(())
 }
 
-fn insert_experimenters_as_users_to_db(experimenters: &Vec<Experimenter>, config: &Config, db_client: &mut Client)
+fn insert_experimenters_as_users_to_db(experimenters: &Vec<Experimenter>, config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<(), String>
 {
     //add experimenter as a user
     for experimenter in experimenters.iter()
     {
         //println!("Experimenter: {} {} ({})", experimenter.firstName, experimenter.lastName, experimenter.piFlag.as_ref().unwrap_or(&"N".to_string()));
-        let pi_user: database::User = database::User::from_experimenter(experimenter, config.db_access_control.get("Visitor").unwrap());
-        let result = database::insert_user(db_client, &pi_user);
-        if result.is_err()
-        {
-            println!("Error inserting user {} {}: {:?}", pi_user.first_name, pi_user.last_name, result.err().unwrap());
-        }
-        else 
-        {
-            println!("Inserted user {} {}", pi_user.first_name, pi_user.last_name);
-        }
+        let pi_user: database::User = database::User::from_experimenter(experimenter, config.db_access_control.get("Visitor").unwrap()).map_err(|e| format!("Error: {}", e))?;
+        db_writer.insert_user(&pi_user).map_err(|e| format!("Error inserting user {} {}: {:?}", pi_user.first_name, pi_user.last_name, e))?;
+        println!("Inserted user {} {}", pi_user.first_name, pi_user.last_name);
     }
+    Ok(())
 }
 
-fn link_experimenters_to_dataset(experimenters: &Vec<Experimenter>, dataset_id: i32, proposal_id: i32, config: &Config, db_client: &mut Client)
+fn link_experimenters_to_dataset(experimenters: &Vec<Experimenter>, dataset_id: i32, proposal_id: i32, config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<(), String>
 {
     for experimenter in experimenters.iter()
     {
@@ -251,74 +830,404 @@ fn link_experimenters_to_dataset(experimenters: &Vec<Experimenter>, dataset_id:
             pi_flag = "Y";
         }
         let experimenter_role_id = config.get_experimenter_role_id(pi_flag);
-        let user_badge:i32 = experimenter.badge.parse().expect("Failed to parse string to integer");
-        let db_expr = database::Experimenter::new(dataset_id, user_badge, proposal_id, experimenter_role_id);
-        let result =  database::insert_experimenter(db_client, &db_expr);
-        if result.is_err()
+        let user_badge:i32 = experimenter.badge.parse().map_err(|e| format!("Error parsing badge {}: {:?}", experimenter.badge, e))?;
+        let db_expr = database::Experimenter::new(dataset_id, user_badge, Some(proposal_id), experimenter_role_id);
+        db_writer.insert_experimenter(&db_expr).map_err(|e| format!("Error inserting experimenter {}: {:?}", user_badge, e))?;
+    }
+    Ok(())
+}
+
+// Beamline local contact scheduled for the activity, inserted as a staff user. Returns its badge,
+// None if the activity has no local contact with a badge.
+fn insert_local_contact_as_user_to_db(activity: &Activity, config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<Option<i32>, String>
+{
+    let contact = match activity.user.as_ref()
+    {
+        Some(contact) => contact,
+        None => return Ok(None),
+    };
+    let uac = config.db_access_control.get("Staff").ok_or(String::from("Error: user access control Staff is missing"))?;
+    let contact_user = match database::User::from_local_contact(contact, uac)
+    {
+        Some(contact_user) => contact_user,
+        None =>
         {
-            println!("Error inserting experimenter {}: {:?}", user_badge, result.err().unwrap());
+            println!("Local contact {:?} of activity {:?} has no numeric badge {:?}, not linking it", contact.name, activity.activityId, contact.badgeNo);
+            return Ok(None);
         }
-        else 
+    };
+    db_writer.insert_user(&contact_user).map_err(|e| format!("Error inserting local contact {} {}: {:?}", contact_user.first_name, contact_user.last_name, e))?;
+    println!("Inserted local contact {} {}", contact_user.first_name, contact_user.last_name);
+    Ok(Some(contact_user.badge))
+}
+
+fn export_counts_png(analyzed_file: &str, png_dir: &str, dataset_id: i32, config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<(), String>
+{
+    let mut xrf_dataset = data_walker::dataset::XrfDataset::new();
+    if let Err(e) = xrf_dataset.load_from_hdf5(analyzed_file)
+    {
+        println!("Error loading {}: {:?}", analyzed_file, e);
+        return Ok(());
+    }
+    // one sub directory per dataset since scan names repeat between runs
+    let output_dir = Path::new(png_dir).join(dataset_id.to_string());
+    match xrf_dataset.export_counts_png(&output_dir, config.png_scaling)
+    {
+        Ok(saved) => 
+        {
+            for (element, png_path) in saved.iter()
+            {
+                db_writer.insert_dataset_image(&database::DatasetImage::new(dataset_id, analyzed_file, element, png_path)).map_err(|e| format!("Error inserting image {}: {:?}", png_path, e))?;
+            }
+            println!("Saved {} png files for {}", saved.len(), analyzed_file);
+        }
+        Err(e) => 
+        {
+            println!("Error saving png files for {}: {:?}", analyzed_file, e);
+        }
+    }
+    Ok(())
+}
+
+fn link_analyzed_files_to_dataset(analyzed_files: &[data_walker::MyFile], dataset_id: i32, config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<(), String>
+{
+    for analyzed_file in analyzed_files.iter()
+    {
+        let detector = data_walker::get_detector_index(&analyzed_file.name);
+        let db_analyzed = database::AnalyzedFile::new(dataset_id, &analyzed_file.name, detector, analyzed_file.ctime);
+        let inserted = db_writer.insert_analyzed_file(&db_analyzed).map_err(|e| format!("Error inserting analyzed file {}: {:?}", analyzed_file.name, e))?;
+        // already linked by an earlier ingestion
+        if inserted == 0
+        {
+            continue;
+        }
+        println!("Linked analyzed file {} (detector {:?})", analyzed_file.name, detector);
+        if let Some(png_dir) = config.png_export_dir.as_ref()
         {
-            
+            export_counts_png(&analyzed_file.name, png_dir, dataset_id, config, db_writer)?;
         }
     }
+    Ok(())
 }
 
-fn process_found_activity(activity: &Activity, raw_files: &Vec<data_walker::MyFile>, config: &Config, db_client: &mut Client)
+// Any error means the caller has to roll back
+fn process_found_activity(activity: &Activity, dataset_dir: &data_walker::DatasetDir, raw_files: &[&data_walker::MyFile], config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<DatasetCounts, String>
 {
     println!("{:?} {:?}", activity.activityId, activity.experimentId);
     println!{"{:?} {:?} {:?}", activity.beamtime.proposal.gupId, activity.beamtime.proposal.proposalTitle, activity.beamtime.proposalStatus};
 
-    insert_experimenters_as_users_to_db(&activity.beamtime.proposal.experimenters, config, db_client);
+    insert_experimenters_as_users_to_db(&activity.beamtime.proposal.experimenters, config, db_writer)?;
     
-    let result2 = database::insert_proposal(db_client, &database::Proposal::from_proposal(&activity.beamtime.proposal));
-    if result2.is_err()
+    let proposal_id = db_writer.insert_proposal(&database::Proposal::from_proposal(&activity.beamtime.proposal)).map_err(|e| format!("Error inserting proposal {:?}: {:?}", activity.activityId, e))?;
+    if proposal_id == -1
     {
-        println!("Error inserting proposal {:?}: {:?}", activity.activityId, result2.err().unwrap());
+        return Err(format!("Failed to insert proposal {:?}. ID = -1", activity.activityId));
     }
-    else 
+    println!("Inserted proposal {:?} with id {}", activity.activityId, proposal_id);
+    // a database without the role still gets the datasets, only the contact is not linked
+    let local_contact = match (activity.user.as_ref(), config.db_experimenter_roles.get(STR_LOCAL_CONTACT))
     {
-        
-        let proposal_id:i32 = result2.unwrap();
-        println!("Inserted proposal {:?} with id {}", activity.activityId, proposal_id);
-        for raw_file in raw_files
+        (None, _) => None,
+        (Some(_), None) =>
         {
-            println!("found raw dataset file {}", raw_file.name);
-            //let mut xrf_dataset = data_walker::XrfDataset::new();
-            //xrf_dataset.load_from_hdf5(&hdf5_file).unwrap();
-            let scan_type_id = 1; //hard code to step scan. TODO: check if we have netcdf files to tell if fly scan
-            
-            let dataset = database::Dataset::new(config.beamline_id, config.run_id, scan_type_id, &raw_file.name, raw_file.ctime);
-            let result = database::insert_dataset(db_client, &dataset);
-            if result.is_err()
-            {
-                println!("Error inserting dataset {}: {:?}", raw_file.name, result.err().unwrap());
-            }
-            else 
-            {
-                let dataset_id = result.unwrap();
-                println!("Inserted dataset {} with id: {}", raw_file.name, dataset_id);
-                // link experimenter to this dataset
-                if dataset_id > -1
-                {
-                    link_experimenters_to_dataset(&activity.beamtime.proposal.experimenters, dataset_id, proposal_id, config, db_client);
-                }
-                else 
-                {
-                    println!("Failed to insert dataset. ID = -1");    
-                }
-            }
+            println!("Warning: experimenter role {} is missing, run migrate. Not linking the local contact of activity {:?}", STR_LOCAL_CONTACT, activity.activityId);
+            None
         }
-    }
+        (Some(_), Some(role)) => insert_local_contact_as_user_to_db(activity, config, db_writer)?.map(|badge| (badge, role.get_id())),
+    };
+    let link_experimenters = |dataset_id: i32, db_writer: &mut dyn database::DbWriter| -> Result<(), String>
+    {
+        link_experimenters_to_dataset(&activity.beamtime.proposal.experimenters, dataset_id, proposal_id, config, db_writer)?;
+        if let Some((badge, local_contact_role_id)) = local_contact
+        {
+            let db_expr = database::Experimenter::new(dataset_id, badge, Some(proposal_id), local_contact_role_id);
+            db_writer.insert_experimenter(&db_expr).map_err(|e| format!("Error inserting local contact {}: {:?}", badge, e))?;
+        }
+        Ok(())
+    };
+    insert_raw_files(dataset_dir, raw_files, &link_experimenters, config, db_writer)
 }
 
-fn search_for_datasets(direcotry: &str, search_raw_ext: &Vec<String>, search_analyzed_ext: &Vec<String>, cur_depth: u32, config: &mut Config, db_client: &mut Client) -> Result<(), std::io::Error>
+// Staff and commissioning data, linked to the staff users without a proposal
+fn process_staff_data(owners: &[&database::User], dataset_dir: &data_walker::DatasetDir, raw_files: &[&data_walker::MyFile], config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<DatasetCounts, String>
 {
-    let dirs = data_walker::get_dirs(direcotry).unwrap();
-    for dir in dirs
+    let role_id = config.db_experimenter_roles.get(STR_STAFF_DATA).map(|r| r.get_id()).ok_or(format!("Error: experimenter role {} is missing, run migrate", STR_STAFF_DATA))?;
+    let link_staff = |dataset_id: i32, db_writer: &mut dyn database::DbWriter| -> Result<(), String>
     {
-        if let Some(dir_name) = dir
+        for owner in owners.iter()
+        {
+            let db_expr = database::Experimenter::new(dataset_id, owner.badge, None, role_id);
+            db_writer.insert_experimenter(&db_expr).map_err(|e| format!("Error inserting staff experimenter {}: {:?}", owner.badge, e))?;
+        }
+        Ok(())
+    };
+    insert_raw_files(dataset_dir, raw_files, &link_staff, config, db_writer)
+}
+
+// Inserts or updates a dataset for every raw file, link_dataset adds its experimenters
+fn insert_raw_files(dataset_dir: &data_walker::DatasetDir, raw_files: &[&data_walker::MyFile], link_dataset: &dyn Fn(i32, &mut dyn database::DbWriter) -> Result<(), String>, config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<DatasetCounts, String>
+{
+    let mut counts = DatasetCounts::default();
+    for raw_file in raw_files.iter()
+    {
+        println!("found raw dataset file {}", raw_file.name);
+        let header = match dataset_dir.get_header(&raw_file.name)
+        {
+            Ok(header) => Some(header),
+            Err(e) => 
+            {
+                println!("Error reading mda header {}: {}", raw_file.name, e);
+                None
+            }
+        };
+        let scan_kind = data_walker::classify_scan(&raw_file.name, header, &dataset_dir.fly_index);
+        // a database without fly scans stores them as step scans, like before scans were classified
+        let scan_type_id = match (config.get_scan_type_id(scan_kind), config.get_scan_type_id(data_walker::ScanKind::Step))
+        {
+            (Some(id), _) => id,
+            (None, Some(step_id)) => 
+            {
+                println!("Error: could not find scan type {:?} for {}, storing it as {}", scan_kind, raw_file.name, STR_STEP_SCAN);
+                step_id
+            }
+            (None, None) => return Err(format!("Error: could not find scan type {:?} or {} for {}", scan_kind, STR_STEP_SCAN, raw_file.name)),
+        };
+        if config.verbose
+        {
+            println!("{} is a {:?} scan", raw_file.name, scan_kind);
+            if let Some(header) = header.filter(|h| h.acquired_points() != h.requested_points())
+            {
+                println!("{} is incomplete, acquired {:?} of {:?} points", raw_file.name, header.acquired_points(), header.requested_points());
+            }
+        }
+
+        let mut dataset = match header
+        {
+            Some(header) => database::Dataset::from_mda_header(config.beamline_id, config.run_id, scan_type_id, &raw_file.name, raw_file.ctime, header),
+            None => database::Dataset::new(config.beamline_id, config.run_id, scan_type_id, &raw_file.name, raw_file.ctime),
+        };
+        if config.use_checksum
+        {
+            match data_walker::file_checksum(&raw_file.name)
+            {
+                Ok(checksum) => dataset.set_checksum(checksum),
+                Err(e) => println!("Error computing checksum of {}: {:?}", raw_file.name, e),
+            }
+        }
+        let candidates = db_writer.get_datasets_by_path(&raw_file.name, dataset.get_checksum()).map_err(|e| format!("Error searching for dataset {}: {:?}", raw_file.name, e))?;
+        // a checksum match was moved here only if its stored path is gone, otherwise this file is a copy
+        let existing = candidates.iter().find(|e| e.get_path() == raw_file.name || !Path::new(e.get_path()).exists()).cloned();
+        if let (None, Some(other)) = (existing.as_ref(), candidates.first())
+        {
+            println!("{} has the same checksum as dataset {} id: {}, inserting it as a copy", raw_file.name, other.get_path(), other.get_id());
+        }
+        let dataset_id = match existing
+        {
+            None => 
+            {
+                let dataset_id = db_writer.insert_dataset(&dataset).map_err(|e| format!("Error inserting dataset {}: {:?}", raw_file.name, e))?;
+                if dataset_id == -1
+                {
+                    return Err(format!("Failed to insert dataset {}. ID = -1", raw_file.name));
+                }
+                println!("Inserted dataset {} with id: {}", raw_file.name, dataset_id);
+                counts.inserted += 1;
+                dataset_id
+            }
+            Some(existing) => 
+            {
+                let diffs = dataset.differences(&existing);
+                match config.existing_mode
+                {
+                    // analyzed files fitted after the first ingestion are still linked
+                    ExistingMode::Skip => 
+                    {
+                        println!("Skipping dataset {}, already in database with id: {}", raw_file.name, existing.get_id());
+                        counts.existing += 1;
+                        existing.get_id()
+                    }
+                    ExistingMode::Report => 
+                    {
+                        if diffs.is_empty()
+                        {
+                            println!("Existing dataset {} id: {} is up to date", raw_file.name, existing.get_id());
+                        }
+                        else 
+                        {
+                            println!("Existing dataset {} id: {} differs: {}", raw_file.name, existing.get_id(), diffs.join(", "));
+                        }
+                        counts.existing += 1;
+                        continue;
+                    }
+                    ExistingMode::Update => 
+                    {
+                        db_writer.update_dataset(existing.get_id(), &dataset).map_err(|e| format!("Error updating dataset {}: {:?}", raw_file.name, e))?;
+                        println!("Updated dataset {} with id: {} {}", raw_file.name, existing.get_id(), diffs.join(", "));
+                        counts.updated += 1;
+                        existing.get_id()
+                    }
+                }
+            }
+        };
+        // link experimenter to this dataset
+        link_dataset(dataset_id, db_writer)?;
+        link_analyzed_files_to_dataset(dataset_dir.get_analyzed_files(&raw_file.name), dataset_id, config, db_writer)?;
+    }
+    Ok(counts)
+}
+
+// All inserts for one proposal are committed together or not at all
+fn process_found_activity_in_transaction(activity: &Activity, dataset_dir: &data_walker::DatasetDir, raw_files: &[&data_walker::MyFile], config: &Config, db_writer: &mut dyn database::DbWriter) -> Result<DatasetCounts, String>
+{
+    in_transaction(db_writer, |db_writer| process_found_activity(activity, dataset_dir, raw_files, config, db_writer))
+}
+fn in_transaction(db_writer: &mut dyn database::DbWriter, process: impl FnOnce(&mut dyn database::DbWriter) -> Result<DatasetCounts, String>) -> Result<DatasetCounts, String>
+{
+    db_writer.begin().map_err(|e| format!("Error starting transaction: {:?}", e))?;
+    match process(db_writer)
+    {
+        Ok(counts) => 
+        {
+            db_writer.commit().map_err(|e| format!("Error committing transaction: {:?}", e))?;
+            Ok(counts)
+        }
+        Err(e) => 
+        {
+            if let Err(rollback_err) = db_writer.rollback()
+            {
+                println!("Error rolling back transaction: {:?}", rollback_err);
+            }
+            Err(e)
+        }
+    }
+}
+
+fn get_activity_window(activity: &Activity) -> Option<(DateTime<Utc>, DateTime<Utc>)>
+{
+    let start_time = synco_runs::parse_time(activity.startTime.as_ref()?).ok()?;
+    let end_time = synco_runs::parse_time(activity.endTime.as_ref()?).ok()?;
+    Some((start_time, end_time))
+}
+
+// Start time from the mda header, None if it is unknown
+fn get_acquisition_time(dataset_dir: &data_walker::DatasetDir, raw_file: &data_walker::MyFile) -> Option<DateTime<Utc>>
+{
+    dataset_dir.get_acquisition_time(&raw_file.name).map(DateTime::<Utc>::from)
+}
+
+// ex: [Some(77001), Some(77002)] and 3 more
+fn list_proposals(proposals: &[Option<i32>]) -> String
+{
+    let listed = format!("{:?}", &proposals[..proposals.len().min(NUM_PROPOSALS_LISTED)]);
+    match proposals.len() > NUM_PROPOSALS_LISTED
+    {
+        true => format!("{} and {} more", listed, proposals.len() - NUM_PROPOSALS_LISTED),
+        false => listed,
+    }
+}
+
+// Splits raw files by the activity whose start and end time contain the acquisition time.
+// Files inside none or several windows or without an acquisition time are returned as (file, reason) instead of guessed.
+fn assign_files_to_activities<'a>(activities: &[&Activity], dataset_dir: &'a data_walker::DatasetDir) -> (Vec<Vec<&'a data_walker::MyFile>>, Vec<(String, String)>)
+{
+    let windows: Vec<Option<(DateTime<Utc>, DateTime<Utc>)>> = activities.iter().map(|a| get_activity_window(a)).collect();
+    let mut assigned = vec![Vec::new(); activities.len()];
+    let mut ambiguous = Vec::new();
+    for raw_file in dataset_dir.raw_files.iter()
+    {
+        let acquired = match get_acquisition_time(dataset_dir, raw_file)
+        {
+            Some(acquired) => acquired,
+            None => 
+            {
+                ambiguous.push((raw_file.name.clone(), String::from("no acquisition time in the mda header")));
+                continue;
+            }
+        };
+        let matching: Vec<usize> = windows.iter().enumerate()
+            .filter(|(_, w)| w.map(|(start, end)| start <= acquired && acquired <= end).unwrap_or(false))
+            .map(|(i, _)| i)
+            .collect();
+        let proposals: Vec<Option<i32>> = activities.iter().map(|a| a.beamtime.proposal.gupId).collect();
+        match matching.len()
+        {
+            1 => assigned[matching[0]].push(raw_file),
+            0 => ambiguous.push((raw_file.name.clone(), format!("acquired {} outside the activities of proposals {}", acquired, list_proposals(&proposals)))),
+            _ => ambiguous.push((raw_file.name.clone(), format!("acquired {} inside several activities of proposals {}", acquired, list_proposals(&matching.iter().map(|&i| proposals[i]).collect::<Vec<_>>())))),
+        }
+    }
+    (assigned, ambiguous)
+}
+
+// Asks the operator about a directory that matches no activity and saves the answer as an override.
+// Returns true if an override was added.
+fn resolve_unmatched_dir(dir_path: &str, pi_name: &str, dataset_dir: &data_walker::DatasetDir, config: &mut Config) -> bool
+{
+    let acquired: Vec<DateTime<Utc>> = dataset_dir.raw_files.iter().filter_map(|raw_file| get_acquisition_time(dataset_dir, raw_file)).collect();
+    let acquired_range = match (acquired.iter().min(), acquired.iter().max())
+    {
+        (Some(first), Some(last)) => Some((*first, *last)),
+        _ => None,
+    };
+    let acquired_label = match acquired_range
+    {
+        Some((first, last)) => format!("acquired {} to {}", first.format("%Y-%m-%d %H:%M"), last.format("%Y-%m-%d %H:%M")),
+        None => String::from("acquisition time unknown"),
+    };
+    let summary = format!("{} raw files, {} with analyzed files, {}", dataset_dir.raw_files.len(), dataset_dir.analyzed_files.len(), acquired_label);
+    let candidates = config.get_resolve_candidates(pi_name, acquired_range);
+    let mut labels = Vec::new();
+    let mut targets = Vec::new();
+    for (activity, reason) in candidates.iter()
+    {
+        let proposal = &activity.beamtime.proposal;
+        let pi = proposal.experimenters.iter().find(|e| e.piFlag.as_deref() == Some("Y"));
+        let pi_label = pi.map(|e| format!("{} {}", e.firstName, e.lastName)).unwrap_or(String::from("no PI"));
+        labels.push(format!("{}, proposal {:?} {:?}, {} to {} ({})", pi_label, proposal.gupId, proposal.proposalTitle.as_deref().unwrap_or(""), activity.startTime.as_deref().unwrap_or("?"), activity.endTime.as_deref().unwrap_or("?"), reason));
+        targets.push((proposal.gupId, activity.activityId));
+    }
+    // the override path is a glob pattern, [ * and ? in directory names must match literally
+    let path = std::fs::canonicalize(dir_path).map(|p| p.display().to_string()).unwrap_or(dir_path.to_string());
+    let mut entry = overrides::DirOverride { path: glob::Pattern::escape(&path), ..Default::default() };
+    match resolve::ask(dir_path, &summary, &labels)
+    {
+        // proposal ids stay valid when the schedule is reloaded, activity ids only if there is no proposal
+        resolve::Decision::Pick(index) => match targets[index]
+        {
+            (Some(gup_id), _) => entry.proposal = Some(gup_id),
+            (None, activity_id) => entry.activity = activity_id,
+        },
+        resolve::Decision::Staff => entry.staff = true,
+        resolve::Decision::Skip =>
+        {
+            println!("skipped {}", dir_path);
+            return false;
+        }
+        resolve::Decision::Quit =>
+        {
+            config.resolve = false;
+            return false;
+        }
+    }
+    let description = entry.describe();
+    if let Some(store) = config.overrides.as_mut()
+    {
+        if let Err(e) = store.add(entry)
+        {
+            println!("Error saving override for {}: {:?}", dir_path, e);
+            return false;
+        }
+        println!("saved override {} for {}", description, dir_path);
+    }
+    true
+}
+
+fn search_for_datasets(direcotry: &str, search_raw_ext: &Vec<String>, search_analyzed_ext: &Vec<String>, cur_depth: u32, config: &mut Config, db_writer: &mut dyn database::DbWriter) -> Result<(), std::io::Error>
+{
+    let dirs = data_walker::get_dirs(direcotry)?;
+    for dir in dirs
+    {
+        if let Some(dir_name) = dir
         {
             if config.verbose
             {
@@ -336,15 +1245,119 @@ fn search_for_datasets(direcotry: &str, search_raw_ext: &Vec<String>, search_ana
                     if let Some(pi_name) = path.file_stem()
                     {
                         //println!("{}", last_folder.to_str().unwrap());
-                        let (found_activity, found_experiementer) = config.search_for_pi_activity(pi_name.to_str().unwrap());
-                        if found_activity.is_some() && found_experiementer.is_some()
+                        let analyzed_dir = path.join(STR_IMG_DAT);
+                        let dataset_dir = data_walker::DatasetDir::new(&dir_name, raw_files, analyzed_dir.to_str().unwrap(), search_analyzed_ext);
+                        config.report.add_scanned(dataset_dir.raw_files.len());
+                        loop
                         {
-                            let activity = found_activity.unwrap();
-                            process_found_activity(activity, &raw_files, config, db_client);
-                        }
-                        else 
-                        {
-                            println!("Error: could not find pi activity for {}", pi_name.to_str().unwrap());
+                            let (activities, by_time) = match config.attribute_dir(direcotry, pi_name.to_str().unwrap())
+                            {
+                                Attribution::Activities(activities, by_time) => (activities, by_time),
+                                Attribution::Staff(owners) =>
+                                {
+                                    let raw_files: Vec<&data_walker::MyFile> = dataset_dir.raw_files.iter().collect();
+                                    let label = format!("{} (staff data)", dir_name);
+                                    match in_transaction(db_writer, |db_writer| process_staff_data(&owners, &dataset_dir, &raw_files, config, db_writer))
+                                    {
+                                        Ok(counts) => config.summary.committed.push((label, counts)),
+                                        Err(e) =>
+                                        {
+                                            println!("{}", e);
+                                            println!("Rolled back {}", label);
+                                            config.summary.rolled_back.push((label, e));
+                                        }
+                                    }
+                                    break;
+                                }
+                                Attribution::Unmatched(reason, detail) =>
+                                {
+                                    println!("Error: {}", detail);
+                                    // overrides are checked before PI names, so a decision settles an ambiguous name
+                                    if reason == report::Reason::AmbiguousPi && config.resolve && resolve_unmatched_dir(direcotry, pi_name.to_str().unwrap(), &dataset_dir, config)
+                                    {
+                                        continue;
+                                    }
+                                    let candidates = config.get_report_candidates(pi_name.to_str().unwrap());
+                                    config.report.add(direcotry, dataset_dir.raw_files.len(), reason, &detail, candidates);
+                                    break;
+                                }
+                                Attribution::NoMatch => (Vec::new(), false),
+                            };
+                            if activities.len() > 0
+                            {
+                                let (assigned, ambiguous) = match activities.len()
+                                {
+                                    1 if !by_time => (vec![dataset_dir.raw_files.iter().collect()], Vec::new()),
+                                    _ => assign_files_to_activities(&activities, &dataset_dir),
+                                };
+                                for (file_name, reason) in ambiguous.iter()
+                                {
+                                    println!("Error: not ingesting {}, {}", file_name, reason);
+                                }
+                                let mut committed = Vec::new();
+                                let mut rolled_back = Vec::new();
+                                // (files, reason, detail) for the unmatched data report
+                                let mut unmatched = Vec::new();
+                                if ambiguous.len() > 0
+                                {
+                                    let detail = match ambiguous.len()
+                                    {
+                                        1 => ambiguous[0].1.clone(),
+                                        num => format!("{} files, first {}", num, ambiguous[0].1),
+                                    };
+                                    unmatched.push((ambiguous.len(), report::Reason::AmbiguousTime, detail));
+                                }
+                                for (activity, activity_files) in activities.iter().zip(assigned.iter())
+                                {
+                                    if activity_files.is_empty()
+                                    {
+                                        continue;
+                                    }
+                                    if let Err((reason, detail)) = check_experimenters(&activity.beamtime.proposal.experimenters, config)
+                                    {
+                                        println!("Error: not ingesting {} files of {} for proposal {:?}, {}", activity_files.len(), dir_name, activity.beamtime.proposal.gupId, detail);
+                                        unmatched.push((activity_files.len(), reason, format!("proposal {:?}: {}", activity.beamtime.proposal.gupId, detail)));
+                                        continue;
+                                    }
+                                    let mut label = format!("{} (proposal {:?})", dir_name, activity.beamtime.proposal.gupId);
+                                    if by_time
+                                    {
+                                        label = format!("{} (proposal {:?} by acquisition time)", dir_name, activity.beamtime.proposal.gupId);
+                                        println!("attributed {} files of {} to activity {:?} proposal {:?}", activity_files.len(), dir_name, activity.activityId, activity.beamtime.proposal.gupId);
+                                    }
+                                    match process_found_activity_in_transaction(activity, &dataset_dir, activity_files, config, db_writer)
+                                    {
+                                        Ok(counts) => committed.push((label, counts)),
+                                        Err(e) => 
+                                        {
+                                            println!("{}", e);
+                                            println!("Rolled back {}", label);
+                                            unmatched.push((activity_files.len(), report::Reason::ProposalInsertFailure, format!("proposal {:?}: {}", activity.beamtime.proposal.gupId, e)));
+                                            rolled_back.push((label, e));
+                                        }
+                                    }
+                                }
+                                config.summary.committed.extend(committed);
+                                config.summary.rolled_back.extend(rolled_back);
+                                config.summary.ambiguous.extend(ambiguous);
+                                let candidates = config.get_report_candidates(pi_name.to_str().unwrap());
+                                for (num_files, reason, detail) in unmatched
+                                {
+                                    config.report.add(direcotry, num_files, reason, &detail, candidates.clone());
+                                }
+                            }
+                            // ask again with the override the operator chose
+                            else if config.resolve && resolve_unmatched_dir(direcotry, pi_name.to_str().unwrap(), &dataset_dir, config)
+                            {
+                                continue;
+                            }
+                            else
+                            {
+                                println!("Error: could not find pi activity for {}", pi_name.to_str().unwrap());
+                                let candidates = config.get_report_candidates(pi_name.to_str().unwrap());
+                                config.report.add(direcotry, dataset_dir.raw_files.len(), report::Reason::NoPiMatch, "directory name matches no PI of the schedule", candidates);
+                            }
+                            break;
                         }
                     }
                     else 
@@ -356,95 +1369,482 @@ fn search_for_datasets(direcotry: &str, search_raw_ext: &Vec<String>, search_ana
             else if cur_depth > 0
             {
                 let new_depth = cur_depth - 1;
-                let _ = search_for_datasets(&dir_name, search_raw_ext, search_analyzed_ext, new_depth, config, db_client);
+                let _ = search_for_datasets(&dir_name, search_raw_ext, search_analyzed_ext, new_depth, config, db_writer);
             }               
         }
     }
     Ok(())
 }
 
+fn get_search_ext() -> (Vec<String>, Vec<String>)
+{
+    let mut analyzed_search_ext: Vec<String> = Vec::new();
+    analyzed_search_ext.push(".h5".to_owned());
+    for i in 0..NUM_DETECTORS
+    {
+        let mut h5_ext = ".h5".to_owned();
+        h5_ext.push_str(&i.to_string());
+        analyzed_search_ext.push(h5_ext);
+    }
+
+    let mut raw_search_ext: Vec<String> = Vec::new();
+    raw_search_ext.push(".mda".to_owned());
+    (raw_search_ext, analyzed_search_ext)
+}
+
+fn load_beam_schedule(source: &dyn schedule_source::ScheduleSource, run: &str, beamline: &str) -> Result<Vec<Activity>, IngestError>
+{
+    let beam_schedule = source.get_activities_json(run, beamline).map_err(IngestError::Schedule)?;
+    serde_json::from_str(&beam_schedule).map_err(|e| IngestError::Failed(format!("Error parsing beam schedule: {:?}", e)))
+}
+
+fn apply_ingest_args(config: &mut Config, args: &Args, credentials: &credentials::Credentials) -> Result<(), String>
+{
+    config.existing_mode = args.existing;
+    config.use_checksum = args.checksum;
+    config.name_match_threshold = args.name_match_threshold;
+    config.attribution_mode = args.attribution;
+    config.report_path = Some(args.report.clone());
+    for staff_dir in args.staff_dirs.iter()
+    {
+        config.staff_dir_patterns.push(glob::Pattern::new(staff_dir).map_err(|e| format!("Error in --staff-dirs {}: {}", staff_dir, e))?);
+    }
+    config.staff_badge = args.staff_badge;
+    if let Some(overrides_file) = args.overrides.as_ref()
+    {
+        let store = overrides::OverrideStore::load(overrides_file, args.resolve).map_err(|e| format!("Error loading overrides {}: {:?}", overrides_file, e))?;
+        println!("loaded {} directory overrides from {}", store.entries.len(), overrides_file);
+        config.overrides = Some(store);
+        config.resolve = args.resolve;
+    }
+    match get_schedule_source(args, None, credentials)
+    {
+        Ok(source) => config.schedule_source = Some(source),
+        Err(e) => println!("{}", e),
+    }
+    if args.export_counts_png && args.test
+    {
+        println!("Dry run: skipping png export");
+    }
+    else if args.export_counts_png
+    {
+        config.png_export_dir = Some(args.png_dir.clone());
+        if let Some(pct) = args.png_percentile
+        {
+            config.png_scaling = data_walker::PngScaling::Percentile(pct);
+        }
+    }
+    Ok(())
+}
+
+// Ingest one run of one beamline, the lookup tables in config must already be loaded
+fn ingest_run(search_dir: &str, num_recursive: u32, run: &str, beamline: &str, schedule_file: Option<&str>, config: &mut Config, db_writer: &mut dyn database::DbWriter) -> Result<(), IngestError>
+{
+    let file_source;
+    let source: &dyn schedule_source::ScheduleSource = match schedule_file
+    {
+        Some(schedule_file) => 
+        {
+            file_source = schedule_source::FileSource::new(schedule_file);
+            &file_source
+        }
+        None => config.schedule_source.as_deref().ok_or(IngestError::Failed(String::from("Error: no schedule source")))?,
+    };
+    config.activities = load_beam_schedule(source, run, beamline)?;
+    config.init_run_info(run, beamline);
+    config.report.start_run(run, beamline);
+    if config.beamline_id == -1 || config.run_id == -1
+    {
+        return Err(IngestError::Failed(String::from("Error: could not find beamline id or run id")));
+    }
+    let (raw_search_ext, analyzed_search_ext) = get_search_ext();
+    search_for_datasets(search_dir, &raw_search_ext, &analyzed_search_ext, num_recursive, config, db_writer).map_err(|e| IngestError::Failed(format!("Error searching {}: {:?}", search_dir, e)))
+}
+
+// Every job fails without ingesting if the database tables could not be loaded, the summary still lists them
+fn run_batch(jobs: &Vec<batch::BatchJob>, config: &mut Config, db_writer: &mut dyn database::DbWriter, db_tables: &Result<(), String>)
+{
+    let mut run_summaries = Vec::new();
+    for job in jobs.iter()
+    {
+        println!("Batch: ingesting run {} of {} from {}", job.run, job.beamline, job.search_dir);
+        let result = match db_tables
+        {
+            Ok(_) => ingest_run(&job.search_dir, job.num_recursive, &job.run, &job.beamline, job.schedule_file.as_deref(), config, db_writer),
+            Err(e) => Err(IngestError::Failed(e.clone())),
+        };
+        let summary = std::mem::take(&mut config.summary);
+        match &result
+        {
+            Ok(_) => summary.print(),
+            Err(e) => println!("{}", e),
+        }
+        // every other job would be refused as well
+        let unauthorized = match &result
+        {
+            Err(IngestError::Schedule(e)) => e.is_unauthorized(),
+            _ => false,
+        };
+        run_summaries.push((job, result, summary));
+        if unauthorized
+        {
+            println!("Batch: stopping, the scheduling api refused the token");
+            break;
+        }
+    }
+    println!("Batch summary:");
+    for (job, result, summary) in run_summaries.iter()
+    {
+        match result
+        {
+            Ok(_) => println!("{} {}: {}", job.beamline, job.run, summary.totals()),
+            Err(e) => println!("{} {}: {}", job.beamline, job.run, e),
+        }
+    }
+    config.save_report();
+}
+
+// Jobs for --search-dir, beamline and run not given on the command line are detected from the path.
+// A beamline root gets one job per run directory known in the database.
+fn get_search_jobs(args: &Args, config: &Config) -> Result<Vec<batch::BatchJob>, String>
+{
+    let search_dir = args.search_dir.clone().unwrap();
+    let mut beamline = args.beamline.clone();
+    let mut run = args.run.clone();
+    if beamline.is_none() || run.is_none()
+    {
+        let rules = path_rules::PathRules::load(args.path_rules.as_deref()).map_err(|e| format!("Error loading path rules: {:?}", e))?;
+        let detected = match rules.detect(&search_dir)
+        {
+            Some(detected) => detected,
+            None => return Err(format!("Error: could not detect beamline and run from {}, use --beamline and --run", search_dir)),
+        };
+        println!("detected beamline {} run {:?} from {}", detected.beamline, detected.run, search_dir);
+        beamline = beamline.or(Some(detected.beamline));
+        run = run.or(detected.run);
+    }
+    let beamline = beamline.unwrap();
+    if let Some(run) = run
+    {
+        return Ok(vec![batch::BatchJob { beamline: beamline, run: run, search_dir: search_dir, num_recursive: args.num_recursive, schedule_file: args.filename.clone() }]);
+    }
+    if args.filename.is_some()
+    {
+        return Err(String::from("Error: --filename can only be used with a single run"));
+    }
+    let mut jobs = Vec::new();
+    let dirs = data_walker::get_dirs(&search_dir).map_err(|e| format!("Error reading {}: {:?}", search_dir, e))?;
+    for dir_name in dirs.into_iter().flatten()
+    {
+        let run_name = Path::new(&dir_name).file_name().unwrap().to_string_lossy().to_string();
+        if config.db_sync_runs.contains_key(&run_name)
+        {
+            jobs.push(batch::BatchJob { beamline: beamline.clone(), run: run_name, search_dir: dir_name, num_recursive: args.num_recursive, schedule_file: None });
+        }
+        else if config.verbose
+        {
+            println!("skipping {}, not a known run", dir_name);
+        }
+    }
+    jobs.sort_by(|a, b| a.run.cmp(&b.run));
+    Ok(jobs)
+}
+
+fn ingest_jobs(jobs: &Vec<batch::BatchJob>, config: &mut Config, db_writer: &mut dyn database::DbWriter)
+{
+    if jobs.len() == 1
+    {
+        let job = &jobs[0];
+        match ingest_run(&job.search_dir, job.num_recursive, &job.run, &job.beamline, job.schedule_file.as_deref(), config, db_writer)
+        {
+            Ok(_) => config.summary.print(),
+            Err(e) => println!("{}", e),
+        }
+        config.save_report();
+    }
+    else 
+    {
+        run_batch(jobs, config, db_writer, &Ok(()));
+    }
+}
+
+fn fill_beamtime_requests(requests_json: &str, config: &Config, db_client: &mut Client)
+{
+    let requests = match beamtime::parse_beamtime_requests(requests_json)
+    {
+        Ok(requests) => requests,
+        Err(e) => 
+        {
+            println!("Error parsing beamtime requests: {:?}", e);
+            return;
+        }
+    };
+    let mut num_inserted = 0;
+    for request in requests.iter()
+    {
+        let proposal = match database::Proposal::from_beamtime_request(request)
+        {
+            Some(proposal) => proposal,
+            None => 
+            {
+                println!("Error: beamtime request {:?} has no proposal id", request.beamtimeId);
+                continue;
+            }
+        };
+        if let Err(e) = database::insert_proposal(db_client, &proposal)
+        {
+            println!("Error inserting proposal {}: {:?}", proposal.id, e);
+            continue;
+        }
+        let db_request = match database::BeamtimeRequest::from_beamtime_request(request, proposal.id, config.run_id, config.beamline_id)
+        {
+            Some(db_request) => db_request,
+            None => 
+            {
+                println!("Error: beamtime request for proposal {} has no beamtime id", proposal.id);
+                continue;
+            }
+        };
+        match database::insert_beamtime_request(db_client, &db_request)
+        {
+            Ok(_) => 
+            {
+                println!("Proposal {} requested {:?} granted {:?} scheduled {:?} shifts", proposal.id, request.requestedShifts, request.grantedShifts, request.beamlineScheduledShifts);
+                num_inserted += 1;
+            }
+            Err(e) => println!("Error inserting beamtime request for proposal {}: {:?}", proposal.id, e),
+        }
+    }
+    println!("Stored {} of {} beamtime requests", num_inserted, requests.len());
+}
+
 //#[tokio::main] 
 //async fn main() 
 fn main()
 {
     let args = Args::parse();
 
-    let psql_conn_str = env::var("SVC_PSQL_CONN_STR").unwrap_or(String::from("postgresql://localhost/mydata"));
-    let mut db_client = Client::connect(&psql_conn_str, NoTls).unwrap();
-    
-    if args.query_db_users
+    // the mock server does not need a database
+    if let Some(Commands::MockServer { address, dir, fail_with }) = args.command.as_ref()
     {
-        database::print_all_user(&mut db_client).unwrap();
+        let result = match dir
+        {
+            Some(dir) => mock_server::serve(address, &schedule_source::DirectorySource::new(dir), fail_with),
+            None => mock_server::serve(address, &mock_server::canned_fixtures(), fail_with),
+        };
+        if let Err(e) = result
+        {
+            println!("{}", e);
+        }
         return;
     }
-    if args.search_dir.is_some()
+
+    let credentials = match credentials::Credentials::load(args.credentials.as_deref())
+    {
+        Ok(credentials) => credentials,
+        Err(e) => 
+        {
+            println!("{}", e);
+            return;
+        }
+    };
+    if args.verbose
+    {
+        credentials.print();
+    }
+    let (pg_config, tls_options) = match credentials.get_pg_config(args.verbose)
     {
-        if args.run.is_some() && args.beamline.is_some()
+        Ok(config) => config,
+        Err(e) => 
         {
-            let mut beam_schedule: String = String::new();
-            if args.filename.is_some()
+            println!("{}", e);
+            return;
+        }
+    };
+    if let Some(Commands::Migrate { create_db, .. }) = args.command
+    {
+        if create_db
+        {
+            match migrate::create_database(&pg_config, &tls_options)
             {
-                let filename = args.filename.clone().unwrap();
-                println!("reading from file {}", filename);
-                beam_schedule = read_json_from_file(&filename).unwrap();
-                if beam_schedule.is_empty()
+                Ok(true) => println!("Created database"),
+                Ok(false) => println!("Database already exists"),
+                Err(e) => 
                 {
-                    println!("Error: file {} is empty", filename);
+                    println!("Error creating database: {}", e);
                     return;
                 }
-                /*
-                if args.verbose
+            }
+        }
+    }
+    let mut db_client = match db_tls::connect(&pg_config, &tls_options)
+    {
+        Ok(db_client) => db_client,
+        Err(e) => 
+        {
+            println!("Error connecting to {}: {}", credentials::redact_conn_str(&credentials.psql_conn_str), e);
+            return;
+        }
+    };
+
+    if let Some(Commands::Migrate { baseline, .. }) = args.command
+    {
+        if baseline
+        {
+            match migrate::baseline(&mut db_client)
+            {
+                Ok(true) => println!("Marked existing schema as version 1"),
+                Ok(false) => println!("Database already has a schema version, not marking it"),
+                Err(e) => 
                 {
-                    println!("file contents: {}", beam_schedule);
+                    println!("Error marking schema version: {:?}", e);
+                    return;
                 }
-                */
             }
-            else 
+        }
+        match migrate::run_migrations(&mut db_client)
+        {
+            Ok(applied) => println!("Applied {} migrations, schema version is {}", applied.len(), migrate::latest_version()),
+            Err(e) => println!("{}", e),
+        }
+        return;
+    }
+
+    if let Some(Commands::SyncRuns { filename }) = args.command.as_ref()
+    {
+        let runs_json = match get_schedule_source(&args, filename.as_ref(), &credentials).and_then(|source| source.get_runs_json().map_err(|e| e.to_string()))
+        {
+            Ok(runs_json) => runs_json,
+            Err(e) => 
             {
-                let run = args.run.clone().unwrap();
-                let beamline = args.beamline.clone().unwrap();
-                
-                let mut url_path = STR_URL_ACTIVITY_HEADER.to_owned();
-                url_path.push_str(&run);
-                url_path.push_str("/");
-                url_path.push_str(&beamline);
-                println!("reading from url {}", url_path);
-                beam_schedule = read_json_from_url(&url_path).unwrap();
+                println!("{}", e);
+                return;
             }
-
-            //let activities: Vec<activity::Activity> = serde_json::from_str(&beam_schedule).unwrap();
-
-            let mut analyzed_search_ext: Vec<String> = Vec::new();
-            analyzed_search_ext.push(".h5".to_owned());
-            for i in 0..NUM_DETECTORS
+        };
+        match synco_runs::fill_syncotron_runs(&mut db_client, &runs_json)
+        {
+            Ok(report) => report.print(),
+            Err(e) => println!("Error syncing runs: {:?}", e),
+        }
+        return;
+    }
+    
+    if let Some(Commands::BeamtimeRequests { beamline, run, filename }) = args.command.as_ref()
+    {
+        let requests_json = match get_schedule_source(&args, filename.as_ref(), &credentials).and_then(|source| source.get_beamtime_requests_json(run, beamline).map_err(|e| e.to_string()))
+        {
+            Ok(requests_json) => requests_json,
+            Err(e) => 
             {
-                let mut h5_ext = ".h5".to_owned();
-                h5_ext.push_str(&i.to_string());
-                analyzed_search_ext.push(h5_ext);
+                println!("{}", e);
+                return;
             }
+        };
+        let mut config = Config::new(args.verbose);
+        if let Err(e) = config.load_db_tables(&mut db_client)
+        {
+            println!("{}", e);
+            return;
+        }
+        config.init_run_info(run, beamline);
+        if config.beamline_id == -1 || config.run_id == -1
+        {
+            println!("Error: could not find beamline id or run id");
+            return;
+        }
+        fill_beamtime_requests(&requests_json, &config, &mut db_client);
+        return;
+    }
 
-            let mut raw_search_ext: Vec<String> = Vec::new();
-            raw_search_ext.push(".mda".to_owned());
-
-            let mut config = Config::new(&beam_schedule,  args.verbose);
-            database::get_all_staff_users(&mut db_client, &mut config.db_staff).unwrap();
-            database::get_access_control(&mut db_client, &mut config.db_access_control).unwrap();
-            database::get_sync_runs(&mut db_client, &mut config.db_sync_runs).unwrap();
-            database::get_experimenter_roles(&mut db_client, &mut config.db_experimenter_roles).unwrap();
-            database::get_scan_types(&mut db_client, &mut config.db_scan_types).unwrap();
-            database::get_beamlines(&mut db_client, &mut config.db_beamlines).unwrap();
-
-            config.init_run_info(&args.run.unwrap(), &args.beamline.unwrap());
+    if let Some(Commands::Batch { manifest }) = args.command.as_ref()
+    {
+        let manifest = match batch::Manifest::load(manifest)
+        {
+            Ok(manifest) => manifest,
+            Err(e) => 
+            {
+                println!("Error loading manifest {}: {:?}", manifest, e);
+                return;
+            }
+        };
+        let jobs = manifest.jobs();
+        let mut config = Config::new(args.verbose);
+        if let Err(e) = apply_ingest_args(&mut config, &args, &credentials)
+        {
+            println!("{}", e);
+            return;
+        }
+        let db_tables = config.load_db_tables(&mut db_client);
+        if args.test
+        {
+            let mut recorder = dry_run::DryRunRecorder::new(Some(&mut db_client));
+            run_batch(&jobs, &mut config, &mut recorder, &db_tables);
+            recorder.print();
+            if let Some(json_path) = args.dry_run_json.as_ref()
+            {
+                match recorder.save_json(json_path)
+                {
+                    Ok(_) => println!("Saved dry run records to {}", json_path),
+                    Err(e) => println!("Error saving dry run records to {}: {:?}", json_path, e),
+                }
+            }
+        }
+        else 
+        {
+            run_batch(&jobs, &mut config, &mut db_client, &db_tables);
+        }
+        return;
+    }
 
-            if config.beamline_id == -1 || config.run_id == -1
+    if args.query_db_users
+    {
+        database::print_all_user(&mut db_client).unwrap();
+        return;
+    }
+    if args.search_dir.is_some()
+    {
+        let mut config = Config::new(args.verbose);
+        if let Err(e) = apply_ingest_args(&mut config, &args, &credentials)
+        {
+            println!("{}", e);
+            return;
+        }
+        if let Err(e) = config.load_db_tables(&mut db_client)
+        {
+            println!("{}", e);
+            return;
+        }
+        let jobs = match get_search_jobs(&args, &config)
+        {
+            Ok(jobs) => jobs,
+            Err(e) => 
             {
-                panic!("Could not find beamline id or run id . Exiting");
+                println!("{}", e);
+                return;
             }
-            search_for_datasets(args.search_dir.as_ref().unwrap(), &raw_search_ext, &analyzed_search_ext, args.num_recursive, &mut config, &mut db_client).unwrap();
+        };
+        if jobs.is_empty()
+        {
+            println!("Error: no run directories found in {}", args.search_dir.as_ref().unwrap());
+            return;
         }
-        else
+        if args.test
+        {
+            let mut recorder = dry_run::DryRunRecorder::new(Some(&mut db_client));
+            ingest_jobs(&jobs, &mut config, &mut recorder);
+            recorder.print();
+            if let Some(json_path) = args.dry_run_json.as_ref()
+            {
+                match recorder.save_json(json_path)
+                {
+                    Ok(_) => println!("Saved dry run records to {}", json_path),
+                    Err(e) => println!("Error saving dry run records to {}: {:?}", json_path, e),
+                }
+            }
+        }
+        else 
         {
-            println!("Error: --run and --beamline must be specified when using --search-dir");  
+            ingest_jobs(&jobs, &mut config, &mut db_client);
         }
     }
     else 
@@ -453,3 +1853,82 @@ fn main()
     }
 
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn png_percentile_range()
+    {
+        assert_eq!(parse_png_percentile("2.5"), Ok(2.5));
+        assert!(parse_png_percentile("0").is_err());
+        assert!(parse_png_percentile("50").is_err());
+        assert!(parse_png_percentile("-1").is_err());
+        assert!(parse_png_percentile("NaN").is_err());
+        assert!(parse_png_percentile("abc").is_err());
+    }
+
+    // schedule of the mock server with a second proposal whose PI is another Smith
+    fn fixtures_with_two_smiths() -> schedule_source::FixtureSource
+    {
+        let mut fixtures = mock_server::canned_fixtures();
+        let json = fixtures.activities.get(&(String::from("2025-1"), String::from("2-ID-E"))).unwrap().clone();
+        let mut activities: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        let mut other = activities[0].clone();
+        other["activityId"] = serde_json::json!(102);
+        other["beamtime"]["proposal"]["gupId"] = serde_json::json!(77002);
+        other["beamtime"]["proposal"]["experimenters"][0]["badge"] = serde_json::json!("300003");
+        other["beamtime"]["proposal"]["experimenters"][0]["firstName"] = serde_json::json!("John");
+        activities.push(other);
+        fixtures.add_activities("2025-1", "2-ID-E", &serde_json::to_string(&activities).unwrap());
+        fixtures
+    }
+
+    #[test]
+    fn attribute_pi_directories()
+    {
+        let run_dir = std::env::temp_dir().join(format!("mic_db_fill_{}_attribute", std::process::id())).join("2025-1");
+        for dir_name in ["Smith", "Zzyzx"]
+        {
+            std::fs::create_dir_all(run_dir.join(dir_name)).unwrap();
+        }
+        let smith_path = run_dir.join("Smith").to_string_lossy().to_string();
+        let other_path = run_dir.join("Zzyzx").to_string_lossy().to_string();
+
+        let mut config = Config::new(false);
+        config.activities = match load_beam_schedule(&mock_server::canned_fixtures(), "2025-1", "2-ID-E")
+        {
+            Ok(activities) => activities,
+            Err(e) => panic!("{}", e),
+        };
+        let activities = config.search_for_pi_activities("Smith").unwrap();
+        assert_eq!(activities.iter().map(|a| a.activityId).collect::<Vec<_>>(), vec![Some(101)]);
+        match config.attribute_dir(&smith_path, "Smith")
+        {
+            Attribution::Activities(activities, by_time) =>
+            {
+                assert_eq!(activities.len(), 1);
+                assert!(!by_time);
+            }
+            _ => panic!("Smith should match the PI of activity 101"),
+        }
+        assert!(config.search_for_pi_activities("Zzyzx").unwrap().is_empty());
+        assert!(matches!(config.attribute_dir(&other_path, "Zzyzx"), Attribution::NoMatch));
+
+        config.activities = match load_beam_schedule(&fixtures_with_two_smiths(), "2025-1", "2-ID-E")
+        {
+            Ok(activities) => activities,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(config.search_for_pi_activities("Smith").is_err());
+        match config.attribute_dir(&smith_path, "Smith")
+        {
+            Attribution::Unmatched(reason, _) => assert!(reason == report::Reason::AmbiguousPi),
+            _ => panic!("Smith should be ambiguous between two PIs"),
+        }
+
+        std::fs::remove_dir_all(run_dir.parent().unwrap()).unwrap();
+    }
+}
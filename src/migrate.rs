@@ -0,0 +1,110 @@
+use postgres::Client;
+use crate::db_tls;
+
+// Schema shipped with the binary. Append new migrations, never edit one that was released.
+struct Migration
+{
+    version: i32,
+    name: &'static str,
+    sql: &'static str,
+}
+
+static MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "initial_schema", sql: include_str!("migrations/0001_initial_schema.sql") },
+    Migration { version: 2, name: "scan_metadata", sql: include_str!("migrations/0002_scan_metadata.sql") },
+    Migration { version: 3, name: "analyzed_files", sql: include_str!("migrations/0003_analyzed_files.sql") },
+    Migration { version: 4, name: "dataset_images", sql: include_str!("migrations/0004_dataset_images.sql") },
+    Migration { version: 5, name: "dataset_checksums", sql: include_str!("migrations/0005_dataset_checksums.sql") },
+    Migration { version: 6, name: "run_names", sql: include_str!("migrations/0006_run_names.sql") },
+    Migration { version: 7, name: "beamtime_requests", sql: include_str!("migrations/0007_beamtime_requests.sql") },
+    Migration { version: 8, name: "staff_data", sql: include_str!("migrations/0008_staff_data.sql") },
+    Migration { version: 9, name: "local_contact", sql: include_str!("migrations/0009_local_contact.sql") },
+];
+
+static SCHEMA_VERSION_TABLE: &'static str = "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at TIMESTAMPTZ NOT NULL DEFAULT now())";
+
+fn quote_identifier(name: &str) -> String
+{
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// Connects to the maintenance database of the server and creates the database named in the connection string.
+// Returns false if it already existed.
+pub fn create_database(pg_config: &postgres::Config, tls_options: &db_tls::TlsOptions) -> Result<bool, String>
+{
+    let mut pg_config = pg_config.clone();
+    let db_name = match pg_config.get_dbname()
+    {
+        Some(name) => name.to_string(),
+        None =>
+        {
+            println!("Error: no database name in connection string");
+            return Ok(false);
+        }
+    };
+    pg_config.dbname("postgres");
+    let mut admin_client = db_tls::connect(&pg_config, tls_options)?;
+    let rows = admin_client.query("SELECT 1 FROM pg_database WHERE datname = $1", &[&db_name]).map_err(|e| e.to_string())?;
+    if !rows.is_empty()
+    {
+        return Ok(false);
+    }
+    admin_client.batch_execute(&format!("CREATE DATABASE {}", quote_identifier(&db_name))).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+pub fn get_schema_version(db_client: &mut Client) -> Result<i32, postgres::Error>
+{
+    db_client.batch_execute(SCHEMA_VERSION_TABLE)?;
+    let row = db_client.query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[])?;
+    Ok(row.get(0))
+}
+
+pub fn latest_version() -> i32
+{
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// Tables of the initial schema in a database that has no schema version yet
+fn has_unversioned_tables(db_client: &mut Client) -> Result<bool, postgres::Error>
+{
+    let row = db_client.query_one("SELECT to_regclass('users') IS NOT NULL OR to_regclass('datasets') IS NOT NULL", &[])?;
+    Ok(row.get(0))
+}
+
+// Marks a database created before migrations were shipped as having the initial schema, without running it
+pub fn baseline(db_client: &mut Client) -> Result<bool, postgres::Error>
+{
+    if get_schema_version(db_client)? > 0
+    {
+        return Ok(false);
+    }
+    let initial = &MIGRATIONS[0];
+    db_client.execute("INSERT INTO schema_version (version, name) VALUES ($1, $2)", &[&initial.version, &initial.name])?;
+    Ok(true)
+}
+
+// Applies every migration newer than the current schema version, each one in its own transaction
+pub fn run_migrations(db_client: &mut Client) -> Result<Vec<i32>, String>
+{
+    let current_version = get_schema_version(db_client).map_err(|e| format!("Error reading schema version: {:?}", e))?;
+    if current_version == 0 && has_unversioned_tables(db_client).map_err(|e| format!("Error reading tables: {:?}", e))?
+    {
+        return Err(String::from("Error: the database has tables but no schema version, run migrate --baseline first"));
+    }
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current_version)
+    {
+        println!("Applying migration {:04} {}", migration.version, migration.name);
+        let apply = |db_client: &mut Client| -> Result<(), postgres::Error>
+        {
+            let mut transaction = db_client.transaction()?;
+            transaction.batch_execute(migration.sql)?;
+            transaction.execute("INSERT INTO schema_version (version, name) VALUES ($1, $2)", &[&migration.version, &migration.name])?;
+            transaction.commit()
+        };
+        apply(db_client).map_err(|e| format!("Error applying migration {:04} {}: {:?}", migration.version, migration.name, e))?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
@@ -0,0 +1,86 @@
+-- Tables as they were before migrations were shipped, existing databases are marked with migrate --baseline
+
+CREATE TABLE user_access_control (
+    id SERIAL PRIMARY KEY,
+    level TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE users (
+    badge INTEGER PRIMARY KEY,
+    username TEXT NOT NULL,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    institution TEXT NOT NULL DEFAULT '',
+    email TEXT NOT NULL DEFAULT '',
+    user_access_control_id INTEGER NOT NULL REFERENCES user_access_control (id)
+);
+
+CREATE TABLE proposals (
+    id INTEGER PRIMARY KEY,
+    title TEXT NOT NULL,
+    proprietaryFlag TEXT,
+    mailInFlag TEXT,
+    status TEXT
+);
+
+CREATE TABLE beamlines (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    acronym TEXT NOT NULL UNIQUE,
+    old_acronym TEXT NOT NULL DEFAULT '',
+    division TEXT NOT NULL DEFAULT '',
+    link TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE syncotron_runs (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    start_timestamp TIMESTAMPTZ NOT NULL,
+    end_timestamp TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE scan_type (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE experiment_roles (
+    id SERIAL PRIMARY KEY,
+    role TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE datasets (
+    id SERIAL PRIMARY KEY,
+    path TEXT NOT NULL,
+    acquisition_timestamp TIMESTAMPTZ NOT NULL,
+    beamline_id INTEGER NOT NULL REFERENCES beamlines (id),
+    syncotron_run_id INTEGER NOT NULL REFERENCES syncotron_runs (id),
+    scan_type_id INTEGER NOT NULL REFERENCES scan_type (id)
+);
+
+CREATE TABLE experimenters (
+    dataset_id INTEGER NOT NULL REFERENCES datasets (id) ON DELETE CASCADE,
+    user_badge INTEGER NOT NULL REFERENCES users (badge),
+    proposal_id INTEGER NOT NULL REFERENCES proposals (id),
+    experiment_role_id INTEGER NOT NULL REFERENCES experiment_roles (id)
+);
+
+INSERT INTO user_access_control (level, description) VALUES
+    ('Visitor', 'Experimenter from the beamline schedule'),
+    ('Staff', 'Beamline staff');
+
+INSERT INTO experiment_roles (role) VALUES
+    ('Principal Investigator'),
+    ('Co-Investigator');
+
+INSERT INTO scan_type (name, description) VALUES
+    ('Step Scan', 'Positioners stop at every point'),
+    ('Fly Scan', 'Inner positioner moves continuously while detectors are triggered');
+
+INSERT INTO beamlines (name, acronym, division) VALUES
+    ('2-ID-D', '2-ID-D', 'XSD'),
+    ('2-ID-E', '2-ID-E', 'XSD'),
+    ('8-BM-B', '8-BM-B', 'XSD'),
+    ('9-ID-B,C', '9-ID-B,C', 'XSD');
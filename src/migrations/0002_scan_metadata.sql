@@ -0,0 +1,5 @@
+-- Scan rank, dimensions and points read from the mda header of each dataset
+
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS scan_rank INTEGER;
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS scan_dimensions INTEGER[];
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS scan_points INTEGER[];
@@ -0,0 +1,9 @@
+-- XRF-Maps outputs fitted from a raw dataset
+
+CREATE TABLE IF NOT EXISTS analyzed_files (
+    id SERIAL PRIMARY KEY,
+    dataset_id INTEGER NOT NULL REFERENCES datasets (id) ON DELETE CASCADE,
+    path TEXT NOT NULL UNIQUE,
+    detector INTEGER,
+    processed_timestamp TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Per element counts png files exported from the analyzed files of a dataset
+
+CREATE TABLE IF NOT EXISTS dataset_images (
+    id SERIAL PRIMARY KEY,
+    dataset_id INTEGER NOT NULL REFERENCES datasets (id) ON DELETE CASCADE,
+    analyzed_path TEXT NOT NULL,
+    element TEXT NOT NULL,
+    path TEXT NOT NULL UNIQUE
+);
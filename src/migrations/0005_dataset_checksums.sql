@@ -0,0 +1,21 @@
+-- Checksums and unique paths to find datasets that are already in the database
+
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS checksum TEXT;
+
+CREATE INDEX IF NOT EXISTS datasets_checksum_idx ON datasets (checksum);
+
+-- Older ingestions could add a path more than once, keep the first dataset and move its experimenters over
+CREATE TEMPORARY TABLE duplicate_datasets AS
+    SELECT id, MIN(id) OVER (PARTITION BY path) AS keep_id FROM datasets;
+DELETE FROM duplicate_datasets WHERE id = keep_id;
+UPDATE experimenters e SET dataset_id = d.keep_id FROM duplicate_datasets d WHERE e.dataset_id = d.id;
+DELETE FROM datasets WHERE id IN (SELECT id FROM duplicate_datasets);
+DROP TABLE duplicate_datasets;
+
+CREATE UNIQUE INDEX IF NOT EXISTS datasets_path_idx ON datasets (path);
+
+DELETE FROM experimenters a USING experimenters b
+    WHERE a.ctid > b.ctid AND a.dataset_id = b.dataset_id AND a.user_badge = b.user_badge
+    AND a.proposal_id = b.proposal_id AND a.experiment_role_id = b.experiment_role_id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS experimenters_unique_idx ON experimenters (dataset_id, user_badge, proposal_id, experiment_role_id);
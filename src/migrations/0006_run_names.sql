@@ -0,0 +1,11 @@
+-- sync-runs upserts runs by name
+
+-- Keep the first run of a name and move its datasets over
+CREATE TEMPORARY TABLE duplicate_runs AS
+    SELECT id, MIN(id) OVER (PARTITION BY name) AS keep_id FROM syncotron_runs;
+DELETE FROM duplicate_runs WHERE id = keep_id;
+UPDATE datasets d SET syncotron_run_id = r.keep_id FROM duplicate_runs r WHERE d.syncotron_run_id = r.id;
+DELETE FROM syncotron_runs WHERE id IN (SELECT id FROM duplicate_runs);
+DROP TABLE duplicate_runs;
+
+CREATE UNIQUE INDEX IF NOT EXISTS syncotron_runs_name_idx ON syncotron_runs (name);
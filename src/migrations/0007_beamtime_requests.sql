@@ -0,0 +1,15 @@
+-- Shifts requested, granted and scheduled for each proposal on a beamline
+
+CREATE TABLE IF NOT EXISTS beamtime_requests (
+    beamtime_id INTEGER NOT NULL,
+    proposal_id INTEGER NOT NULL REFERENCES proposals (id),
+    syncotron_run_id INTEGER NOT NULL REFERENCES syncotron_runs (id),
+    beamline_id INTEGER NOT NULL REFERENCES beamlines (id),
+    requested_shifts INTEGER,
+    granted_shifts INTEGER,
+    scheduled_shifts INTEGER,
+    total_scheduled_shifts INTEGER,
+    time_unit TEXT,
+    status TEXT,
+    PRIMARY KEY (beamtime_id, beamline_id)
+);
@@ -0,0 +1,9 @@
+-- Staff and commissioning data belongs to staff users without a proposal
+
+ALTER TABLE experimenters ALTER COLUMN proposal_id DROP NOT NULL;
+
+-- NULL proposals would never conflict in the old index
+DROP INDEX IF EXISTS experimenters_unique_idx;
+CREATE UNIQUE INDEX experimenters_unique_idx ON experimenters (dataset_id, user_badge, COALESCE(proposal_id, -1), experiment_role_id);
+
+INSERT INTO experiment_roles (role) SELECT 'Staff Data' WHERE NOT EXISTS (SELECT 1 FROM experiment_roles WHERE role = 'Staff Data');
@@ -0,0 +1,3 @@
+-- Beamline local contact scheduled for an activity, linked to its datasets
+
+INSERT INTO experiment_roles (role) SELECT 'Local Contact' WHERE NOT EXISTS (SELECT 1 FROM experiment_roles WHERE role = 'Local Contact');
@@ -0,0 +1,205 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tiny_http::{Header, Response, Server};
+use crate::schedule_source::{self, ApiError, FixtureSource, ScheduleSource};
+
+// Small stand in for the scheduling API so ingestion can run without network.
+// Point --api-url at http://<address>/ to use it.
+
+static STR_FIXTURE_RUN: &'static str = "2025-1";
+static STR_FIXTURE_BEAMLINE: &'static str = "2-ID-E";
+
+// Canned responses for run 2025-1 on 2-ID-E, the experimenters match a PI directory named Smith
+pub fn canned_fixtures() -> FixtureSource
+{
+    let mut fixtures = FixtureSource::default();
+    fixtures.add_activities(STR_FIXTURE_RUN, STR_FIXTURE_BEAMLINE, include_str!("mock_fixtures/activity.json"));
+    fixtures.add_beamtime_requests(STR_FIXTURE_RUN, STR_FIXTURE_BEAMLINE, include_str!("mock_fixtures/beamtime_requests.json"));
+    fixtures.runs = Some(include_str!("mock_fixtures/runs.json").to_string());
+    fixtures
+}
+
+fn decode_path_part(part: &str) -> String
+{
+    // beamline names like 9-ID-B,C may come percent encoded
+    part.replace("%2C", ",").replace("%2c", ",").replace("%20", " ")
+}
+
+// Returns the response body for an api path, or None if the path is not an endpoint
+fn route(source: &dyn ScheduleSource, url: &str) -> Option<Result<String, ApiError>>
+{
+    let path = url.split('?').next().unwrap_or("").trim_matches('/');
+    let run_and_beamline = |prefix: &str| -> Option<(String, String)>
+    {
+        let rest = path.strip_prefix(prefix)?.trim_start_matches('/');
+        let mut parts = rest.splitn(2, '/');
+        let run = parts.next().filter(|r| !r.is_empty())?;
+        let beamline = parts.next().filter(|b| !b.is_empty())?;
+        Some((decode_path_part(run), decode_path_part(beamline)))
+    };
+    if let Some((run, beamline)) = run_and_beamline(schedule_source::STR_PATH_ACTIVITY)
+    {
+        return Some(source.get_activities_json(&run, &beamline));
+    }
+    if let Some((run, beamline)) = run_and_beamline(schedule_source::STR_PATH_BEAMTIME)
+    {
+        return Some(source.get_beamtime_requests_json(&run, &beamline));
+    }
+    if path == schedule_source::STR_PATH_RUNS
+    {
+        return Some(source.get_runs_json());
+    }
+    None
+}
+
+// Serves requests until the process is stopped, the first requests are answered with the fail_with status codes
+pub fn serve(address: &str, source: &dyn ScheduleSource, fail_with: &[u16]) -> Result<(), String>
+{
+    let server = Server::http(address).map_err(|e| format!("Error starting mock server on {}: {:?}", address, e))?;
+    println!("mock scheduling api listening on http://{}/", address);
+    serve_requests(&server, source, fail_with, &AtomicUsize::new(0));
+    Ok(())
+}
+
+fn serve_requests(server: &Server, source: &dyn ScheduleSource, fail_with: &[u16], num_requests: &AtomicUsize)
+{
+    let json_header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    for request in server.incoming_requests()
+    {
+        let url = request.url().to_string();
+        let request_idx = num_requests.fetch_add(1, Ordering::SeqCst);
+        let response = match route(source, &url)
+        {
+            Some(_) if request_idx < fail_with.len() => Response::from_string(format!("Error: mock status {}", fail_with[request_idx])).with_status_code(fail_with[request_idx]),
+            Some(Ok(body)) => Response::from_string(body).with_header(json_header.clone()),
+            Some(Err(e)) =>
+            {
+                println!("{}", e);
+                Response::from_string(e.to_string()).with_status_code(404)
+            }
+            None => Response::from_string(format!("Error: unknown endpoint {}", url)).with_status_code(404),
+        };
+        println!("{} {} -> {}", request.method(), url, response.status_code().0);
+        if let Err(e) = request.respond(response)
+        {
+            println!("Error responding to {}: {:?}", url, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use crate::schedule_source::{HttpOptions, HttpSource};
+
+    // Mock server with the canned fixtures on a free port, returns its url and the number of requests it received
+    fn start_failing_mock_server(fail_with: &[u16]) -> (String, Arc<AtomicUsize>)
+    {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        let num_requests = Arc::new(AtomicUsize::new(0));
+        let server_requests = num_requests.clone();
+        let fail_with = fail_with.to_vec();
+        std::thread::spawn(move || serve_requests(&server, &canned_fixtures(), &fail_with, &server_requests));
+        (format!("http://{}", address), num_requests)
+    }
+
+    fn start_mock_server() -> String
+    {
+        start_failing_mock_server(&[]).0
+    }
+
+    fn http_source_with_retries(api_url: &str, retries: u32) -> HttpSource
+    {
+        let options = HttpOptions { timeout: Duration::from_secs(5), retries: retries, backoff: Duration::from_millis(10) };
+        HttpSource::new(api_url, None, options, "Bearer test").unwrap()
+    }
+
+    fn http_source(api_url: &str) -> HttpSource
+    {
+        http_source_with_retries(api_url, 0)
+    }
+
+    #[test]
+    fn load_beam_schedule_from_mock_server()
+    {
+        let source = http_source(&start_mock_server());
+        let activities = crate::load_beam_schedule(&source, STR_FIXTURE_RUN, STR_FIXTURE_BEAMLINE).ok().unwrap();
+        let expected: Vec<crate::Activity> = serde_json::from_str(include_str!("mock_fixtures/activity.json")).unwrap();
+        assert_eq!(activities.len(), expected.len());
+        assert_eq!(activities[0].activityId, expected[0].activityId);
+        assert_eq!(activities[0].beamtime.proposal.gupId, Some(77001));
+        let pi = activities[0].beamtime.proposal.experimenters.iter().find(|e| e.piFlag.as_deref() == Some("Y")).unwrap();
+        assert_eq!(pi.lastName, "Smith");
+    }
+
+    #[test]
+    fn beamtime_requests_and_runs_from_mock_server()
+    {
+        let source = http_source(&start_mock_server());
+        let requests_json = source.get_beamtime_requests_json(STR_FIXTURE_RUN, STR_FIXTURE_BEAMLINE).unwrap();
+        let requests = crate::beamtime::parse_beamtime_requests(&requests_json).unwrap();
+        assert!(!requests.is_empty());
+        assert_eq!(source.get_runs_json().unwrap(), include_str!("mock_fixtures/runs.json"));
+    }
+
+    #[test]
+    fn unknown_run_is_not_found()
+    {
+        let source = http_source(&start_mock_server());
+        match source.get_activities_json("1999-1", STR_FIXTURE_BEAMLINE)
+        {
+            Err(ApiError::NotFound(_)) => (),
+            other => panic!("expected not found, got {:?}", other.map(|body| body.len())),
+        }
+    }
+
+    #[test]
+    fn refused_token_is_not_retried()
+    {
+        for status in [401, 403]
+        {
+            let (api_url, num_requests) = start_failing_mock_server(&[status]);
+            match http_source_with_retries(&api_url, 3).get_runs_json()
+            {
+                Err(ApiError::Unauthorized(_, code)) => assert_eq!(code.as_u16(), status),
+                other => panic!("expected unauthorized for {}, got {:?}", status, other.map(|body| body.len())),
+            }
+            assert_eq!(num_requests.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn server_errors_are_retried_with_backoff()
+    {
+        let (api_url, num_requests) = start_failing_mock_server(&[503, 500]);
+        let start = Instant::now();
+        let runs_json = http_source_with_retries(&api_url, 3).get_runs_json().unwrap();
+        assert_eq!(runs_json, include_str!("mock_fixtures/runs.json"));
+        assert_eq!(num_requests.load(Ordering::SeqCst), 3);
+        // 10ms before the first retry, 20ms before the second
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn server_errors_give_up_after_last_retry()
+    {
+        let (api_url, num_requests) = start_failing_mock_server(&[503, 503, 503, 503]);
+        match http_source_with_retries(&api_url, 2).get_activities_json(STR_FIXTURE_RUN, STR_FIXTURE_BEAMLINE)
+        {
+            Err(ApiError::Server(_, code)) => assert_eq!(code.as_u16(), 503),
+            other => panic!("expected server error, got {:?}", other.map(|body| body.len())),
+        }
+        assert_eq!(num_requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn fixture_source_needs_no_server()
+    {
+        let activities = crate::load_beam_schedule(&canned_fixtures(), STR_FIXTURE_RUN, STR_FIXTURE_BEAMLINE).ok().unwrap();
+        assert_eq!(activities.len(), 1);
+        assert!(route(&canned_fixtures(), "/unknown/endpoint").is_none());
+    }
+}
@@ -0,0 +1,258 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+// Scores how well a data directory name matches an experimenter, 1.0 is an exact match.
+// Directories are named by hand so they may differ in case, accents, separators
+// or carry the first initial, ex: Smith_J, smith, Garcia-Lopez, Muller, vanderBerg.
+// At the default threshold the same name, last name with the first initial, last and first name and one part
+// of a double last name are accepted. A last name inside a longer name (Smith_lab), a different initial and
+// misspellings only become candidates.
+
+// scores below this are not worth showing as candidates
+pub static MIN_CANDIDATE_SCORE: f64 = 0.3;
+pub static DEFAULT_THRESHOLD: f64 = 0.85;
+// staff folders are only attributed by the same last name, last name and first initial or last and first name
+pub static STAFF_THRESHOLD: f64 = 0.98;
+
+pub static SCORE_SAME_LAST_NAME: f64 = 1.0;
+pub static SCORE_LAST_NAME_AND_INITIAL: f64 = 0.98;
+pub static SCORE_LAST_AND_FIRST_NAME: f64 = 0.98;
+pub static SCORE_PART_OF_LAST_NAME: f64 = 0.9;
+pub static SCORE_CONTAINS_LAST_NAME: f64 = 0.8;
+pub static SCORE_DIFFERENT_INITIAL: f64 = 0.6;
+// times the edit distance similarity, which is below 1 for any misspelling, so misspellings stay below
+// the default threshold at any name length, 1 edit in 10 letters scores 0.72
+pub static SCORE_SIMILAR_SPELLING: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct NameScore
+{
+    pub score: f64,
+    pub reason: &'static str,
+}
+
+// letters that do not decompose into a base letter and a combining mark
+fn transliterate(c: char) -> Option<&'static str>
+{
+    match c
+    {
+        'ß' => Some("ss"),
+        'æ' => Some("ae"),
+        'œ' => Some("oe"),
+        'ø' => Some("o"),
+        'ł' => Some("l"),
+        'đ' => Some("d"),
+        'þ' => Some("th"),
+        'ı' => Some("i"),
+        _ => None,
+    }
+}
+
+// Lower case ascii letters and digits only: accents stripped, separators and apostrophes removed
+pub fn normalize_name(name: &str) -> String
+{
+    let mut normalized = String::new();
+    for c in name.to_lowercase().nfd()
+    {
+        if is_combining_mark(c)
+        {
+            continue;
+        }
+        if let Some(replacement) = transliterate(c)
+        {
+            normalized.push_str(replacement);
+        }
+        else if c.is_ascii_alphanumeric()
+        {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+// German spelling without umlauts, Müller -> Mueller
+fn umlaut_variant(name: &str) -> String
+{
+    let expanded: String = name.to_lowercase().chars().map(|c| match c
+    {
+        'ä' => String::from("ae"),
+        'ö' => String::from("oe"),
+        'ü' => String::from("ue"),
+        _ => c.to_string(),
+    }).collect();
+    normalize_name(&expanded)
+}
+
+fn name_tokens(name: &str) -> Vec<String>
+{
+    name.split(|c: char| c == ' ' || c == '-' || c == '_' || c == '.' || c == ',')
+        .map(normalize_name)
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn best(a: NameScore, b: NameScore) -> NameScore
+{
+    if b.score > a.score { b } else { a }
+}
+
+pub fn score_name(dir_name: &str, last_name: &str, first_name: &str) -> NameScore
+{
+    let mut result = NameScore { score: 0.0, reason: "no match" };
+    let dir_full = normalize_name(dir_name);
+    if dir_full.is_empty()
+    {
+        return result;
+    }
+    let mut last_variants = vec![normalize_name(last_name)];
+    let umlaut = umlaut_variant(last_name);
+    if umlaut != last_variants[0]
+    {
+        last_variants.push(umlaut);
+    }
+    last_variants.retain(|l| !l.is_empty());
+    let first = normalize_name(first_name);
+    let first_initial = first.chars().next();
+    let dir_tokens = name_tokens(dir_name);
+
+    // directory is the last name with the first initial before or after it
+    let mut dir_with_initial = None;
+    if dir_tokens.len() >= 2
+    {
+        if dir_tokens.last().unwrap().len() == 1
+        {
+            dir_with_initial = Some((dir_tokens[..dir_tokens.len() - 1].concat(), dir_tokens.last().unwrap().chars().next()));
+        }
+        else if dir_tokens[0].len() == 1
+        {
+            dir_with_initial = Some((dir_tokens[1..].concat(), dir_tokens[0].chars().next()));
+        }
+    }
+
+    for last in last_variants.iter()
+    {
+        if &dir_full == last
+        {
+            return NameScore { score: SCORE_SAME_LAST_NAME, reason: "same last name" };
+        }
+        let mut initial_differs = false;
+        if let Some((dir_last, initial)) = dir_with_initial.as_ref()
+        {
+            if dir_last == last
+            {
+                if *initial == first_initial
+                {
+                    result = best(result, NameScore { score: SCORE_LAST_NAME_AND_INITIAL, reason: "last name and first initial" });
+                }
+                else
+                {
+                    initial_differs = true;
+                    result = best(result, NameScore { score: SCORE_DIFFERENT_INITIAL, reason: "last name with a different initial" });
+                }
+            }
+        }
+        if !first.is_empty() && (dir_full == format!("{}{}", last, first) || dir_full == format!("{}{}", first, last))
+        {
+            result = best(result, NameScore { score: SCORE_LAST_AND_FIRST_NAME, reason: "last and first name" });
+        }
+        // one part of a double last name, ex: Garcia for Garcia-Lopez
+        let last_tokens = name_tokens(last_name);
+        if last_tokens.len() >= 2 && last_tokens.iter().any(|t| t == &dir_full)
+        {
+            result = best(result, NameScore { score: SCORE_PART_OF_LAST_NAME, reason: "part of last name" });
+        }
+        // last name plus something else, ex: Smith_lab
+        if dir_tokens.len() >= 2 && !initial_differs && dir_tokens.iter().any(|t| t == last)
+        {
+            result = best(result, NameScore { score: SCORE_CONTAINS_LAST_NAME, reason: "directory contains last name" });
+        }
+        if dir_full.len() >= 4 && last.len() >= 4 && !initial_differs
+        {
+            let similarity = strsim::normalized_damerau_levenshtein(&dir_full, last);
+            result = best(result, NameScore { score: SCORE_SIMILAR_SPELLING * similarity, reason: "similar spelling" });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn score(dir_name: &str, last_name: &str, first_name: &str) -> f64
+    {
+        score_name(dir_name, last_name, first_name).score
+    }
+
+    #[test]
+    fn normalize_strips_case_accents_and_separators()
+    {
+        assert_eq!(normalize_name("Straße"), "strasse");
+        assert_eq!(normalize_name("Bjørn"), "bjorn");
+        assert_eq!(normalize_name("Müller"), "muller");
+        assert_eq!(normalize_name("Łukasz"), "lukasz");
+        assert_eq!(normalize_name("O'Brien"), "obrien");
+        assert_eq!(normalize_name("García-López"), "garcialopez");
+        assert_eq!(normalize_name("van der Berg"), "vanderberg");
+        assert_eq!(normalize_name("_-."), "");
+    }
+
+    #[test]
+    fn header_examples_are_accepted()
+    {
+        assert_eq!(score("Smith_J", "Smith", "Jane"), SCORE_LAST_NAME_AND_INITIAL);
+        assert_eq!(score("J.Smith", "Smith", "Jane"), SCORE_LAST_NAME_AND_INITIAL);
+        assert_eq!(score("smith", "Smith", "Jane"), SCORE_SAME_LAST_NAME);
+        assert_eq!(score("Garcia-Lopez", "García López", "Ana"), SCORE_SAME_LAST_NAME);
+        assert_eq!(score("Garcia", "Garcia-Lopez", "Ana"), SCORE_PART_OF_LAST_NAME);
+        assert_eq!(score("Muller", "Müller", "Hans"), SCORE_SAME_LAST_NAME);
+        assert_eq!(score("Mueller", "Müller", "Hans"), SCORE_SAME_LAST_NAME);
+        assert_eq!(score("vanderBerg", "van der Berg", "Piet"), SCORE_SAME_LAST_NAME);
+        assert_eq!(score("SmithJane", "Smith", "Jane"), SCORE_LAST_AND_FIRST_NAME);
+    }
+
+    #[test]
+    fn one_edit_misspellings_are_candidates()
+    {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // 1 edit in 10 letters
+        assert!(close(score("Richardsen", "Richardson", "Ann"), 0.72));
+        // swapped letters count as one edit
+        assert!(close(score("Smiht", "Smith", "Jane"), 0.64));
+        // even 1 edit in 18 letters stays a candidate, a lower --name-match-threshold accepts it
+        let misspelled_long = score("Wojciechowskiewicx", "Wojciechowskiewicz", "Jan");
+        assert!(misspelled_long < DEFAULT_THRESHOLD && misspelled_long > 0.75);
+        assert_eq!(score_name("Richardsen", "Richardson", "Ann").reason, "similar spelling");
+    }
+
+    #[test]
+    fn staff_threshold_accepts_full_names_only()
+    {
+        assert!(score("Nobody", "Nobody", "Tom") >= STAFF_THRESHOLD);
+        assert!(score("Nobody_T", "Nobody", "Tom") >= STAFF_THRESHOLD);
+        assert!(score("TomNobody", "Nobody", "Tom") >= STAFF_THRESHOLD);
+        assert!(score("Garcia", "Garcia-Lopez", "Ana") < STAFF_THRESHOLD);
+        assert!(score("Nobody_lab", "Nobody", "Tom") < STAFF_THRESHOLD);
+        assert!(score("Contacts", "Contact", "Lucy") < STAFF_THRESHOLD);
+    }
+
+    #[test]
+    fn candidates_below_default_threshold()
+    {
+        assert_eq!(score("Smith_lab", "Smith", "Jane"), SCORE_CONTAINS_LAST_NAME);
+        assert_eq!(score("Smith_K", "Smith", "Jane"), SCORE_DIFFERENT_INITIAL);
+        let misspelled = score("Smiht", "Smith", "Jane");
+        assert!(misspelled < DEFAULT_THRESHOLD && misspelled >= MIN_CANDIDATE_SCORE);
+        let misspelled_long = score("Kowalsky", "Kowalski", "Jan");
+        assert!(misspelled_long < DEFAULT_THRESHOLD && misspelled_long >= MIN_CANDIDATE_SCORE);
+    }
+
+    #[test]
+    fn unrelated_names_are_not_candidates()
+    {
+        assert!(score("Jones", "Smith", "Jane") < MIN_CANDIDATE_SCORE);
+        assert!(score("commissioning", "Smith", "Jane") < MIN_CANDIDATE_SCORE);
+        assert_eq!(score("", "Smith", "Jane"), 0.0);
+    }
+}
@@ -0,0 +1,352 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+// Curator fixes for directories that do not match their PI by name, checked before name matching.
+// toml:  [[overrides]]
+//        path = "/data1/2idd/2025-1/Smth*"
+//        proposal = 77001
+// csv:   path,proposal,activity,badges,staff   columns by header name, all but path optional,
+//        badges separated by spaces or ';'
+// A path without '/' is matched against the directory name, otherwise against its full path.
+// Paths are glob patterns, the first matching entry wins.
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct DirOverride
+{
+    pub path: String,
+    // GUP proposal id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proposal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<i64>,
+    // activities whose proposal lists any of these experimenter badges, the owners of staff data
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub badges: Vec<i64>,
+    // staff or commissioning data that belongs to no proposal, owned by --staff-badge if badges is empty
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub staff: bool,
+}
+
+fn is_false(value: &bool) -> bool
+{
+    !*value
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct OverrideFile
+{
+    #[serde(default)]
+    overrides: Vec<DirOverride>,
+}
+
+static CSV_COLUMNS: [&'static str; 5] = ["path", "proposal", "activity", "badges", "staff"];
+
+#[derive(Deserialize, Debug)]
+struct CsvRow
+{
+    path: String,
+    #[serde(default)]
+    proposal: Option<i32>,
+    #[serde(default)]
+    activity: Option<i64>,
+    #[serde(default)]
+    badges: Option<String>,
+    #[serde(default)]
+    staff: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OverridesError
+{
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Csv(csv::Error),
+    Pattern(String, glob::PatternError),
+    Invalid(String),
+    UnknownFormat(String),
+}
+
+pub struct OverrideStore
+{
+    pub entries: Vec<DirOverride>,
+    patterns: Vec<glob::Pattern>,
+    file_path: PathBuf,
+}
+
+fn parse_badges(badges: &str) -> Result<Vec<i64>, OverridesError>
+{
+    badges.split(|c: char| c == ';' || c.is_whitespace())
+        .filter(|b| !b.is_empty())
+        .map(|b| b.parse::<i64>().map_err(|_| OverridesError::Invalid(format!("badge {} is not a number", b))))
+        .collect()
+}
+
+fn csv_reader(contents: &str) -> csv::Reader<&[u8]>
+{
+    // rows written before a column was added to the header are shorter than it
+    csv::ReaderBuilder::new().trim(csv::Trim::All).comment(Some(b'#')).flexible(true).from_reader(contents.as_bytes())
+}
+
+fn load_csv(contents: &str) -> Result<Vec<DirOverride>, OverridesError>
+{
+    let mut reader = csv_reader(contents);
+    let mut entries = Vec::new();
+    for row in reader.deserialize()
+    {
+        let row: CsvRow = row.map_err(OverridesError::Csv)?;
+        let badges = match row.badges
+        {
+            Some(badges) => parse_badges(&badges)?,
+            None => Vec::new(),
+        };
+        let staff = match row.staff.as_deref().map(|s| s.to_lowercase()).as_deref()
+        {
+            None | Some("") | Some("false") => false,
+            Some("true") => true,
+            Some(other) => return Err(OverridesError::Invalid(format!("{} staff {} is not true or false", row.path, other))),
+        };
+        entries.push(DirOverride { path: row.path, proposal: row.proposal, activity: row.activity, badges: badges, staff: staff });
+    }
+    Ok(entries)
+}
+
+// Header of an existing csv, None if it has no rows yet
+fn csv_header(contents: &str) -> Result<Option<Vec<String>>, OverridesError>
+{
+    if contents.lines().all(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+    {
+        return Ok(None);
+    }
+    let mut reader = csv_reader(contents);
+    let header = reader.headers().map_err(OverridesError::Csv)?;
+    Ok(Some(header.iter().map(|h| h.to_string()).collect()))
+}
+
+// Header and row for the entry with the columns in header order. Columns the entry needs that the header lacks
+// are added to the header, older rows read them as empty.
+fn csv_row(entry: &DirOverride, old_header: Option<&Vec<String>>) -> Result<(Vec<String>, String), OverridesError>
+{
+    let badges: Vec<String> = entry.badges.iter().map(|b| b.to_string()).collect();
+    let values = [
+        entry.path.clone(),
+        entry.proposal.map(|p| p.to_string()).unwrap_or_default(),
+        entry.activity.map(|a| a.to_string()).unwrap_or_default(),
+        badges.join(";"),
+        if entry.staff { String::from("true") } else { String::new() },
+    ];
+    let mut header = old_header.cloned().unwrap_or(CSV_COLUMNS.iter().map(|c| c.to_string()).collect());
+    for (column, value) in CSV_COLUMNS.iter().zip(values.iter())
+    {
+        if !value.is_empty() && !header.iter().any(|h| h == column)
+        {
+            header.push(column.to_string());
+        }
+    }
+    let row: Vec<&str> = header.iter().map(|h| CSV_COLUMNS.iter().position(|c| c == h).map(|i| values[i].as_str()).unwrap_or("")).collect();
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    writer.write_record(&row).map_err(OverridesError::Csv)?;
+    let row = String::from_utf8(writer.into_inner().map_err(|e| OverridesError::Io(e.into_error()))?).unwrap_or_default();
+    Ok((header, row))
+}
+
+impl DirOverride
+{
+    fn check(&self) -> Result<(), OverridesError>
+    {
+        // badges of staff data are its owners
+        let num_targets = self.proposal.is_some() as u32 + self.activity.is_some() as u32 + (!self.badges.is_empty() && !self.staff) as u32 + self.staff as u32;
+        if num_targets != 1
+        {
+            return Err(OverridesError::Invalid(format!("{} needs exactly one of proposal, activity, badges or staff", self.path)));
+        }
+        Ok(())
+    }
+
+    pub fn describe(&self) -> String
+    {
+        match (self.proposal, self.activity)
+        {
+            (Some(proposal), _) => format!("proposal {}", proposal),
+            (_, Some(activity)) => format!("activity {}", activity),
+            _ if self.staff && self.badges.is_empty() => String::from("staff data"),
+            _ if self.staff => format!("staff data of badges {:?}", self.badges),
+            _ => format!("badges {:?}", self.badges),
+        }
+    }
+}
+
+impl OverrideStore
+{
+    // Format is picked by extension: .toml or .csv. A missing file is an empty store if create_missing is set,
+    // it is written by the first add.
+    pub fn load(file_path: &str, create_missing: bool) -> Result<Self, OverridesError>
+    {
+        let ext = Path::new(file_path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        if ext != "toml" && ext != "csv"
+        {
+            return Err(OverridesError::UnknownFormat(ext));
+        }
+        let mut store = OverrideStore { entries: Vec::new(), patterns: Vec::new(), file_path: PathBuf::from(file_path) };
+        if create_missing && !store.file_path.exists()
+        {
+            return Ok(store);
+        }
+        let contents = fs::read_to_string(file_path).map_err(OverridesError::Io)?;
+        let entries = match ext.as_str()
+        {
+            "toml" => toml::from_str::<OverrideFile>(&contents).map_err(OverridesError::Toml)?.overrides,
+            _ => load_csv(&contents)?,
+        };
+        for entry in entries
+        {
+            store.push(entry)?;
+        }
+        Ok(store)
+    }
+
+    fn push(&mut self, entry: DirOverride) -> Result<(), OverridesError>
+    {
+        entry.check()?;
+        let pattern = glob::Pattern::new(entry.path.trim_end_matches('/')).map_err(|e| OverridesError::Pattern(entry.path.clone(), e))?;
+        self.entries.push(entry);
+        self.patterns.push(pattern);
+        Ok(())
+    }
+
+    // Appends the entry to the file so comments and order of the existing entries are kept
+    pub fn add(&mut self, entry: DirOverride) -> Result<(), OverridesError>
+    {
+        let text = match self.file_path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref()
+        {
+            Some("csv") =>
+            {
+                let contents = fs::read_to_string(&self.file_path).unwrap_or_default();
+                let old_header = csv_header(&contents)?;
+                let (header, row) = csv_row(&entry, old_header.as_ref())?;
+                let separator = if !contents.is_empty() && !contents.ends_with('\n') { "\n" } else { "" };
+                match old_header
+                {
+                    // new, empty or only comments
+                    None => format!("{}{}\n{}", separator, header.join(","), row),
+                    Some(old_header) if old_header != header =>
+                    {
+                        let mut lines: Vec<&str> = contents.lines().collect();
+                        let header_index = lines.iter().position(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#')).unwrap();
+                        let header_line = header.join(",");
+                        lines[header_index] = &header_line;
+                        fs::write(&self.file_path, format!("{}\n{}", lines.join("\n"), row)).map_err(OverridesError::Io)?;
+                        return self.push(entry);
+                    }
+                    Some(_) => format!("{}{}", separator, row),
+                }
+            }
+            _ =>
+            {
+                let block = toml::to_string(&OverrideFile { overrides: vec![entry.clone()] }).map_err(|e| OverridesError::Invalid(e.to_string()))?;
+                format!("\n{}", block)
+            }
+        };
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.file_path).map_err(OverridesError::Io)?;
+        file.write_all(text.as_bytes()).map_err(OverridesError::Io)?;
+        self.push(entry)
+    }
+
+    // First entry matching the directory name or its absolute path
+    pub fn find(&self, dir_path: &str) -> Option<&DirOverride>
+    {
+        let path = fs::canonicalize(dir_path).unwrap_or(Path::new(dir_path).to_path_buf());
+        let dir_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let options = glob::MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+        self.entries.iter().zip(self.patterns.iter())
+            .find(|(entry, pattern)| match entry.path.contains('/')
+            {
+                true => pattern.matches_path_with(&path, options),
+                false => pattern.matches_with(&dir_name, options),
+            })
+            .map(|(entry, _)| entry)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn temp_file(name: &str, contents: Option<&str>) -> String
+    {
+        let path = std::env::temp_dir().join(format!("mic_db_fill_{}_{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        if let Some(contents) = contents
+        {
+            fs::write(&path, contents).unwrap();
+        }
+        path.display().to_string()
+    }
+
+    #[test]
+    fn csv_columns_are_read_by_header_name()
+    {
+        let entries = load_csv("badges,path\n300001;300002,Smith*\n").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "Smith*");
+        assert_eq!(entries[0].badges, vec![300001, 300002]);
+        assert_eq!(entries[0].proposal, None);
+        assert!(!entries[0].staff);
+    }
+
+    #[test]
+    fn csv_staff_column_is_optional()
+    {
+        let entries = load_csv("path,proposal,activity,badges\nSmth*,77001,,\n").unwrap();
+        assert_eq!(entries[0].proposal, Some(77001));
+        assert!(!entries[0].staff);
+        let entries = load_csv("path,staff,badges\ncommiss*,true,900001\n").unwrap();
+        assert!(entries[0].staff);
+        assert_eq!(entries[0].badges, vec![900001]);
+        assert!(load_csv("path,staff\ncommiss*,maybe\n").is_err());
+    }
+
+    #[test]
+    fn add_to_empty_csv_writes_header()
+    {
+        let path = temp_file("empty.csv", Some(""));
+        let mut store = OverrideStore::load(&path, true).unwrap();
+        store.add(DirOverride { path: String::from("Smth*"), proposal: Some(77001), ..Default::default() }).unwrap();
+        let reloaded = OverrideStore::load(&path, false).unwrap();
+        assert_eq!(reloaded.entries.len(), 1);
+        assert_eq!(reloaded.entries[0].proposal, Some(77001));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn add_staff_to_csv_without_staff_column()
+    {
+        let path = temp_file("old.csv", Some("# curated\nproposal,path\n77001,Smth*"));
+        let mut store = OverrideStore::load(&path, false).unwrap();
+        store.add(DirOverride { path: String::from("commiss*"), staff: true, ..Default::default() }).unwrap();
+        let reloaded = OverrideStore::load(&path, false).unwrap();
+        assert_eq!(reloaded.entries.len(), 2);
+        assert_eq!(reloaded.entries[0].proposal, Some(77001));
+        assert!(!reloaded.entries[0].staff);
+        assert_eq!(reloaded.entries[1].path, "commiss*");
+        assert!(reloaded.entries[1].staff);
+        assert!(fs::read_to_string(&path).unwrap().starts_with("# curated\n"));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn add_to_toml_appends_entry()
+    {
+        let path = temp_file("new.toml", None);
+        let mut store = OverrideStore::load(&path, true).unwrap();
+        store.add(DirOverride { path: String::from("Smth*"), activity: Some(101), ..Default::default() }).unwrap();
+        store.add(DirOverride { path: String::from("commiss*"), staff: true, badges: vec![900001], ..Default::default() }).unwrap();
+        let reloaded = OverrideStore::load(&path, false).unwrap();
+        assert_eq!(reloaded.entries.len(), 2);
+        assert_eq!(reloaded.entries[0].activity, Some(101));
+        assert!(reloaded.entries[1].staff);
+        let _ = fs::remove_file(&path);
+    }
+}
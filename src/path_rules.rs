@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::{Component, Path};
+use std::collections::HashMap;
+use serde::Deserialize;
+
+static DEFAULT_PATH_RULES: &'static str = include_str!("default_path_rules.toml");
+
+#[derive(Deserialize, Debug)]
+pub struct PathRule
+{
+    pub pattern: String,
+    // beamline directory name -> beamline name
+    #[serde(default)]
+    pub beamlines: HashMap<String, String>,
+    // beamline for patterns without {dir} or {beamline}
+    pub beamline: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PathRules
+{
+    pub rules: Vec<PathRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DetectedPath
+{
+    pub beamline: String,
+    // None if the path is a beamline root holding run directories
+    pub run: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum PathRulesError
+{
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+}
+
+fn path_components(path: &Path) -> Vec<String>
+{
+    path.components().filter_map(|c| match c
+    {
+        Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+        _ => None,
+    }).collect()
+}
+
+impl PathRule
+{
+    fn match_path(&self, components: &[String]) -> Option<DetectedPath>
+    {
+        let pattern: Vec<&str> = self.pattern.split('/').filter(|s| !s.is_empty()).collect();
+        let is_root = components.len() + 1 == pattern.len() && pattern.last() == Some(&"{run}");
+        if components.len() != pattern.len() && !is_root
+        {
+            return None;
+        }
+        let mut beamline = self.beamline.clone();
+        let mut run = None;
+        for (segment, name) in pattern.iter().zip(components.iter())
+        {
+            match *segment
+            {
+                "{dir}" => beamline = Some(self.beamlines.get(name).unwrap_or(name).clone()),
+                "{beamline}" => beamline = Some(name.clone()),
+                "{run}" => run = Some(name.clone()),
+                "*" => (),
+                literal =>
+                {
+                    if literal != name
+                    {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(DetectedPath { beamline: beamline?, run: run })
+    }
+}
+
+impl PathRules
+{
+    pub fn load(file_path: Option<&str>) -> Result<Self, PathRulesError>
+    {
+        let contents = match file_path
+        {
+            Some(file_path) => fs::read_to_string(file_path).map_err(PathRulesError::Io)?,
+            None => DEFAULT_PATH_RULES.to_string(),
+        };
+        toml::from_str(&contents).map_err(PathRulesError::Toml)
+    }
+
+    // First rule matching the absolute search dir wins
+    pub fn detect(&self, search_dir: &str) -> Option<DetectedPath>
+    {
+        let path = fs::canonicalize(search_dir).unwrap_or(Path::new(search_dir).to_path_buf());
+        let components = path_components(&path);
+        self.rules.iter().find_map(|rule| rule.match_path(&components))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_detected(rules: &PathRules, search_dir: &str, beamline: &str, run: Option<&str>)
+    {
+        let detected = rules.detect(search_dir).unwrap_or_else(|| panic!("no rule matched {}", search_dir));
+        assert_eq!(detected.beamline, beamline, "{}", search_dir);
+        assert_eq!(detected.run.as_deref(), run, "{}", search_dir);
+    }
+
+    #[test]
+    fn shipped_rules()
+    {
+        let rules = PathRules::load(None).unwrap();
+        assert_detected(&rules, "/data1/2idd/2025-1", "2-ID-D", Some("2025-1"));
+        assert_detected(&rules, "/data1/bnp/2022-2/", "9-ID-B,C", Some("2022-2"));
+        assert_detected(&rules, "/data1/8bm", "8-BM-B", None);
+        // a directory missing from beamlines is used as the beamline name
+        assert_detected(&rules, "/data1/2-ID-E/2024-3", "2-ID-E", Some("2024-3"));
+        // PI directories and other mounts match no rule
+        assert!(rules.detect("/data1/2idd/2025-1/Smith").is_none());
+        assert!(rules.detect("/mnt/micdata1/2idd/2025-1").is_none());
+        assert!(rules.detect("/data1").is_none());
+    }
+
+    #[test]
+    fn rules_file()
+    {
+        let file_path = std::env::temp_dir().join(format!("mic_db_fill_{}_path_rules.toml", std::process::id()));
+        fs::write(&file_path, r#"
+[[rules]]
+pattern = "/mnt/*/{dir}/{run}"
+[rules.beamlines]
+2idd = "2-ID-D"
+
+[[rules]]
+pattern = "/export/{beamline}/{run}/raw"
+
+[[rules]]
+pattern = "/local/xfm/{run}"
+beamline = "2-ID-E"
+"#).unwrap();
+        let rules = PathRules::load(Some(&file_path.to_string_lossy())).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        assert_detected(&rules, "/mnt/micdata1/2idd/2025-1", "2-ID-D", Some("2025-1"));
+        assert_detected(&rules, "/mnt/micdata2/2idd", "2-ID-D", None);
+        assert_detected(&rules, "/export/9-ID-B,C/2023-1/raw", "9-ID-B,C", Some("2023-1"));
+        assert_detected(&rules, "/local/xfm/2025-2", "2-ID-E", Some("2025-2"));
+        assert!(rules.detect("/export/9-ID-B,C/2023-1/analysis").is_none());
+        assert!(rules.detect("/data1/2idd/2025-1").is_none());
+        assert!(matches!(PathRules::load(Some("/nonexistent/path_rules.toml")), Err(PathRulesError::Io(_))));
+    }
+}
@@ -0,0 +1,263 @@
+use std::fs;
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+// Directories with raw files that were not attributed to a proposal, with the reason and the nearest
+// PI candidates. Saved after every ingestion as json for scripts and markdown for people.
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason
+{
+    NoPiMatch,
+    // different PIs match the directory name equally well
+    AmbiguousPi,
+    // the override names a proposal, activity or badges that are not in the schedule
+    OverrideWithoutActivity,
+    // staff data without a staff user to own it
+    StaffWithoutOwner,
+    // acquisition time inside none or several activities
+    AmbiguousTime,
+    NoEmail,
+    BadgeParseError,
+    ProposalInsertFailure,
+}
+
+impl Reason
+{
+    fn label(&self) -> &'static str
+    {
+        match self
+        {
+            Reason::NoPiMatch => "no PI match",
+            Reason::AmbiguousPi => "ambiguous PI name",
+            Reason::OverrideWithoutActivity => "override without activity",
+            Reason::StaffWithoutOwner => "staff data without owner",
+            Reason::AmbiguousTime => "ambiguous acquisition time",
+            Reason::NoEmail => "no email",
+            Reason::BadgeParseError => "badge parse error",
+            Reason::ProposalInsertFailure => "proposal insert failure",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Candidate
+{
+    pub name: String,
+    pub proposal: Option<i32>,
+    pub activity: Option<i64>,
+    pub score: f64,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UnmatchedDir
+{
+    pub run: String,
+    pub beamline: String,
+    pub dir: String,
+    pub num_files: usize,
+    pub reason: Reason,
+    pub detail: String,
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RunTotals
+{
+    pub run: String,
+    pub beamline: String,
+    // directories with raw files and their raw files
+    pub num_dirs: usize,
+    pub num_files: usize,
+    pub num_unmatched_dirs: usize,
+    pub num_unmatched_files: usize,
+    pub by_reason: BTreeMap<Reason, usize>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct UnmatchedReport
+{
+    pub unmatched: Vec<UnmatchedDir>,
+    pub totals: Vec<RunTotals>,
+    #[serde(skip)]
+    run: String,
+    #[serde(skip)]
+    beamline: String,
+}
+
+impl UnmatchedReport
+{
+    // Following entries belong to this run and beamline
+    pub fn start_run(&mut self, run: &str, beamline: &str)
+    {
+        self.run = run.to_string();
+        self.beamline = beamline.to_string();
+        self.current_totals();
+    }
+
+    fn current_totals(&mut self) -> &mut RunTotals
+    {
+        let index = match self.totals.iter().position(|t| t.run == self.run && t.beamline == self.beamline)
+        {
+            Some(index) => index,
+            None =>
+            {
+                self.totals.push(RunTotals { run: self.run.clone(), beamline: self.beamline.clone(), ..Default::default() });
+                self.totals.len() - 1
+            }
+        };
+        &mut self.totals[index]
+    }
+
+    // A directory with raw files was searched
+    pub fn add_scanned(&mut self, num_files: usize)
+    {
+        let totals = self.current_totals();
+        totals.num_dirs += 1;
+        totals.num_files += num_files;
+    }
+
+    pub fn add(&mut self, dir: &str, num_files: usize, reason: Reason, detail: &str, candidates: Vec<Candidate>)
+    {
+        let (run, beamline) = (self.run.clone(), self.beamline.clone());
+        let is_new_dir = !self.unmatched.iter().any(|u| u.dir == dir && u.run == run && u.beamline == beamline);
+        let totals = self.current_totals();
+        if is_new_dir
+        {
+            totals.num_unmatched_dirs += 1;
+        }
+        totals.num_unmatched_files += num_files;
+        *totals.by_reason.entry(reason).or_insert(0) += 1;
+        self.unmatched.push(UnmatchedDir { run: run, beamline: beamline, dir: dir.to_string(), num_files: num_files, reason: reason, detail: detail.to_string(), candidates: candidates });
+    }
+
+    pub fn to_markdown(&self) -> String
+    {
+        let mut md = String::from("# Unmatched data\n\n## Totals\n\n");
+        md.push_str("| Run | Beamline | Directories | Files | Unmatched directories | Unmatched files | Reasons |\n");
+        md.push_str("|---|---|---|---|---|---|---|\n");
+        for totals in self.totals.iter()
+        {
+            let reasons: Vec<String> = totals.by_reason.iter().map(|(reason, num)| format!("{}: {}", reason.label(), num)).collect();
+            md.push_str(&format!("| {} | {} | {} | {} | {} | {} | {} |\n", totals.run, totals.beamline, totals.num_dirs, totals.num_files, totals.num_unmatched_dirs, totals.num_unmatched_files, reasons.join(", ")));
+        }
+        for totals in self.totals.iter()
+        {
+            let entries: Vec<&UnmatchedDir> = self.unmatched.iter().filter(|u| u.run == totals.run && u.beamline == totals.beamline).collect();
+            if entries.is_empty()
+            {
+                continue;
+            }
+            md.push_str(&format!("\n## {} {}\n\n", totals.run, totals.beamline));
+            md.push_str("| Directory | Files | Reason | Detail | Nearest candidates |\n");
+            md.push_str("|---|---|---|---|---|\n");
+            for entry in entries
+            {
+                let candidates: Vec<String> = entry.candidates.iter().map(|c| format!("{} (proposal {}, {:.2})", c.name, c.proposal.map(|p| p.to_string()).unwrap_or(String::from("?")), c.score)).collect();
+                md.push_str(&format!("| {} | {} | {} | {} | {} |\n", entry.dir, entry.num_files, entry.reason.label(), entry.detail.replace('|', "\\|"), candidates.join("<br>")));
+            }
+        }
+        md
+    }
+
+    // Writes <base_path>.json and <base_path>.md
+    pub fn save(&self, base_path: &str) -> Result<(), String>
+    {
+        let json_path = format!("{}.json", base_path);
+        let md_path = format!("{}.md", base_path);
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Error serializing unmatched report: {:?}", e))?;
+        fs::write(&json_path, json).map_err(|e| format!("Error writing {}: {:?}", json_path, e))?;
+        fs::write(&md_path, self.to_markdown()).map_err(|e| format!("Error writing {}: {:?}", md_path, e))?;
+        println!("Saved unmatched data report to {} and {}", json_path, md_path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn candidate(name: &str, proposal: i32) -> Candidate
+    {
+        Candidate { name: name.to_string(), proposal: Some(proposal), activity: None, score: 0.6, reason: String::from("different initial") }
+    }
+
+    fn sample_report() -> UnmatchedReport
+    {
+        let mut report = UnmatchedReport::default();
+        report.start_run("2025-1", "2-ID-E");
+        report.add_scanned(10);
+        report.add_scanned(4);
+        report.add_scanned(3);
+        report.add("/data/2025-1/Nobody", 4, Reason::NoPiMatch, "directory name matches no PI of the schedule", vec![candidate("Smith", 1001)]);
+        // one directory split into files of two reasons counts once
+        report.add("/data/2025-1/Smith", 2, Reason::AmbiguousTime, "2 files outside any activity", vec![]);
+        report.add("/data/2025-1/Smith", 1, Reason::ProposalInsertFailure, "proposal 1001: a|b", vec![]);
+        report.start_run("2025-1", "2-ID-D");
+        report.add_scanned(5);
+        report.add("/data/2025-1/Jones", 5, Reason::NoPiMatch, "directory name matches no PI of the schedule", vec![]);
+        report
+    }
+
+    #[test]
+    fn totals_per_run_and_beamline()
+    {
+        let report = sample_report();
+        assert_eq!(report.totals.len(), 2);
+        let totals = &report.totals[0];
+        assert_eq!((totals.run.as_str(), totals.beamline.as_str()), ("2025-1", "2-ID-E"));
+        assert_eq!((totals.num_dirs, totals.num_files), (3, 17));
+        assert_eq!((totals.num_unmatched_dirs, totals.num_unmatched_files), (2, 7));
+        let totals = &report.totals[1];
+        assert_eq!((totals.num_dirs, totals.num_files, totals.num_unmatched_dirs, totals.num_unmatched_files), (1, 5, 1, 5));
+    }
+
+    #[test]
+    fn reasons_are_grouped()
+    {
+        let report = sample_report();
+        let by_reason: Vec<(Reason, usize)> = report.totals[0].by_reason.iter().map(|(r, n)| (*r, *n)).collect();
+        assert_eq!(by_reason, vec![(Reason::NoPiMatch, 1), (Reason::AmbiguousTime, 1), (Reason::ProposalInsertFailure, 1)]);
+        assert_eq!(report.totals[1].by_reason.get(&Reason::NoPiMatch), Some(&1));
+        // starting a run again continues its totals
+        let mut report = report;
+        report.start_run("2025-1", "2-ID-E");
+        report.add("/data/2025-1/Other", 1, Reason::NoPiMatch, "", vec![]);
+        assert_eq!(report.totals.len(), 2);
+        assert_eq!(report.totals[0].by_reason.get(&Reason::NoPiMatch), Some(&2));
+    }
+
+    #[test]
+    fn markdown_output()
+    {
+        let md = sample_report().to_markdown();
+        assert!(md.contains("| 2025-1 | 2-ID-E | 3 | 17 | 2 | 7 | no PI match: 1, ambiguous acquisition time: 1, proposal insert failure: 1 |"));
+        assert!(md.contains("| 2025-1 | 2-ID-D | 1 | 5 | 1 | 5 | no PI match: 1 |"));
+        assert!(md.contains("\n## 2025-1 2-ID-E\n"));
+        assert!(md.contains("| /data/2025-1/Nobody | 4 | no PI match | directory name matches no PI of the schedule | Smith (proposal 1001, 0.60) |"));
+        // pipes in details would end the table cell
+        assert!(md.contains("| proposal 1001: a\\|b |"));
+    }
+
+    #[test]
+    fn json_output()
+    {
+        let base_path = std::env::temp_dir().join(format!("mic_db_fill_{}_report", std::process::id()));
+        let base_path = base_path.to_str().unwrap();
+        sample_report().save(base_path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(format!("{}.json", base_path)).unwrap()).unwrap();
+        assert!(fs::read_to_string(format!("{}.md", base_path)).unwrap().starts_with("# Unmatched data"));
+        fs::remove_file(format!("{}.json", base_path)).unwrap();
+        fs::remove_file(format!("{}.md", base_path)).unwrap();
+        assert_eq!(json["unmatched"].as_array().unwrap().len(), 4);
+        assert_eq!(json["unmatched"][0]["reason"], "no_pi_match");
+        assert_eq!(json["unmatched"][0]["candidates"][0]["proposal"], 1001);
+        assert_eq!(json["totals"][0]["by_reason"]["ambiguous_time"], 1);
+        assert_eq!(json["totals"][1]["num_unmatched_files"], 5);
+        // the current run is internal state
+        assert!(json.get("run").is_none());
+    }
+}
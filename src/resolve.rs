@@ -0,0 +1,64 @@
+use std::io::{self, BufRead, Write};
+
+// Terminal prompt for directories that match no activity. The operator picks one of the candidate
+// activities, skips the directory or marks it as staff data.
+
+pub enum Decision
+{
+    // index into the candidates
+    Pick(usize),
+    Skip,
+    Staff,
+    // stop asking for the rest of the run
+    Quit,
+}
+
+pub fn ask(dir_path: &str, summary: &str, candidates: &[String]) -> Decision
+{
+    println!();
+    println!("unmatched directory {}", dir_path);
+    println!("  {}", summary);
+    if candidates.is_empty()
+    {
+        println!("  no candidate activities");
+    }
+    for (i, candidate) in candidates.iter().enumerate()
+    {
+        println!("  {}) {}", i + 1, candidate);
+    }
+    let prompt = match candidates.len()
+    {
+        0 => String::from("s to skip, t for staff data, q to stop resolving: "),
+        num => format!("pick 1-{}, s to skip, t for staff data, q to stop resolving: ", num),
+    };
+    let stdin = io::stdin();
+    loop
+    {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line)
+        {
+            // end of input, nobody left to ask
+            Ok(0) | Err(_) => return Decision::Quit,
+            Ok(_) => (),
+        }
+        match line.trim()
+        {
+            "s" => return Decision::Skip,
+            "t" => return Decision::Staff,
+            "q" => return Decision::Quit,
+            answer =>
+            {
+                if let Ok(num) = answer.parse::<usize>()
+                {
+                    if num >= 1 && num <= candidates.len()
+                    {
+                        return Decision::Pick(num - 1);
+                    }
+                }
+                println!("unknown choice {}", answer);
+            }
+        }
+    }
+}
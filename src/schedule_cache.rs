@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::schedule_source::ApiError;
+
+// On disk cache of scheduling API responses, one file per endpoint, run and beamline:
+// <cache dir>/<endpoint>/<run>_<beamline>.json
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode
+{
+    // use the cached response if there is one, otherwise fetch and store it
+    Normal,
+    // never fetch, fail if the response is not cached
+    Offline,
+    // always fetch and replace the cached response
+    Refresh,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedResponse
+{
+    endpoint: String,
+    key: Vec<String>,
+    url: String,
+    fetched_at: DateTime<Utc>,
+    body: String,
+}
+
+pub struct ScheduleCache
+{
+    dir: PathBuf,
+    mode: CacheMode,
+    verbose: bool,
+}
+
+// beamline names like 9-ID-B,C are fine in file names but keep path separators out
+fn safe_file_part(name: &str) -> String
+{
+    name.chars().map(|c| if c == '/' || c == '\\' || c == ' ' { '_' } else { c }).collect()
+}
+
+// <endpoint>/<run>_<beamline>.json, also the layout of schedule directories
+pub fn entry_file_name(endpoint: &str, key: &[&str]) -> PathBuf
+{
+    let file_name = match key.is_empty()
+    {
+        true => String::from("all"),
+        false => key.iter().map(|k| safe_file_part(k)).collect::<Vec<String>>().join("_"),
+    };
+    PathBuf::from(safe_file_part(endpoint)).join(format!("{}.json", file_name))
+}
+
+// Saved responses can be plain json or cache entries, return the response body of either
+pub fn response_body(contents: String) -> String
+{
+    match serde_json::from_str::<CachedResponse>(&contents)
+    {
+        Ok(entry) => entry.body,
+        Err(_) => contents,
+    }
+}
+
+impl ScheduleCache
+{
+    pub fn new(dir: &str, mode: CacheMode, verbose: bool) -> Self
+    {
+        ScheduleCache { dir: PathBuf::from(dir), mode: mode, verbose: verbose }
+    }
+
+    fn entry_path(&self, endpoint: &str, key: &[&str]) -> PathBuf
+    {
+        self.dir.join(entry_file_name(endpoint, key))
+    }
+
+    fn read_entry(&self, path: &PathBuf) -> Result<CachedResponse, String>
+    {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Error reading cached response {}: {:?}", path.display(), e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Error parsing cached response {}: {:?}", path.display(), e))
+    }
+
+    fn write_entry(&self, path: &PathBuf, entry: &CachedResponse) -> Result<(), String>
+    {
+        if let Some(parent) = path.parent()
+        {
+            fs::create_dir_all(parent).map_err(|e| format!("Error creating cache dir {}: {:?}", parent.display(), e))?;
+        }
+        let contents = serde_json::to_string_pretty(entry).map_err(|e| format!("Error serializing cached response: {:?}", e))?;
+        fs::write(path, contents).map_err(|e| format!("Error writing cached response {}: {:?}", path.display(), e))
+    }
+
+    // Returns the response body for url, fetched with fetch_url unless the cache can answer
+    pub fn get<F>(&self, endpoint: &str, key: &[&str], url: &str, fetch_url: F) -> Result<String, ApiError>
+        where F: Fn(&str) -> Result<String, ApiError>
+    {
+        let path = self.entry_path(endpoint, key);
+        if self.mode != CacheMode::Refresh && path.exists()
+        {
+            let entry = self.read_entry(&path).map_err(ApiError::Cache)?;
+            println!("using cached {} fetched at {} from {}", endpoint, entry.fetched_at, path.display());
+            return Ok(entry.body);
+        }
+        if self.mode == CacheMode::Offline
+        {
+            return Err(ApiError::Cache(format!("Error: offline and no cached response for {} {:?} in {}", endpoint, key, self.dir.display())));
+        }
+        let body = fetch_url(url)?;
+        let entry = CachedResponse
+        {
+            endpoint: endpoint.to_string(),
+            key: key.iter().map(|k| k.to_string()).collect(),
+            url: url.to_string(),
+            fetched_at: Utc::now(),
+            body: body,
+        };
+        // a failed cache write should not stop the ingestion
+        match self.write_entry(&path, &entry)
+        {
+            Ok(_) =>
+            {
+                if self.verbose
+                {
+                    println!("cached {} in {}", endpoint, path.display());
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+        Ok(entry.body)
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use std::path::PathBuf;
+use std::collections::HashMap;
+use crate::schedule_cache::{self, ScheduleCache};
+
+// Where beamline schedules come from: the scheduling API, a saved file, a directory of saved
+// responses or fixtures held in memory.
+
+pub static STR_DEFAULT_API_URL: &'static str = "https://beam-api.aps.anl.gov/beamline-scheduling/sched-api";
+// endpoint paths below the api url
+pub static STR_PATH_ACTIVITY: &'static str = "activity/findByRunNameAndBeamlineId";
+pub static STR_PATH_BEAMTIME: &'static str = "beamtimeRequests/findBeamtimeRequestsByRunAndBeamline";
+pub static STR_PATH_RUNS: &'static str = "run/getAllRuns";
+
+// endpoint names used for cache and directory entries
+pub static STR_ACTIVITY: &'static str = "activity";
+pub static STR_BEAMTIME_REQUESTS: &'static str = "beamtime_requests";
+pub static STR_RUNS: &'static str = "runs";
+
+pub trait ScheduleSource
+{
+    fn get_activities_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>;
+    fn get_beamtime_requests_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>;
+    fn get_runs_json(&self) -> Result<String, ApiError>;
+}
+
+#[derive(Debug)]
+pub enum ApiError
+{
+    // 401 or 403, SVC_AUTH_STR is missing or wrong
+    Unauthorized(String, reqwest::StatusCode),
+    // 404, usually an unknown run or beamline
+    NotFound(String),
+    // 5xx still failing after all retries
+    Server(String, reqwest::StatusCode),
+    Status(String, reqwest::StatusCode),
+    Timeout(String),
+    Request(String, reqwest::Error),
+    // saved response or fixture missing or unreadable
+    Io(String),
+    // cache entry unreadable or missing when offline
+    Cache(String),
+}
+
+impl fmt::Display for ApiError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            ApiError::Unauthorized(url, status) => write!(f, "Error: scheduling api refused {} with {}, check SVC_AUTH_STR", url, status),
+            ApiError::NotFound(url) => write!(f, "Error: scheduling api has nothing at {}, check the run and beamline names", url),
+            ApiError::Server(url, status) => write!(f, "Error: scheduling api failed {} with {}", url, status),
+            ApiError::Status(url, status) => write!(f, "Error: scheduling api returned {} for {}", status, url),
+            ApiError::Timeout(url) => write!(f, "Error: timed out reading {}", url),
+            ApiError::Request(url, e) => write!(f, "Error: reading {}: {}", url, e),
+            ApiError::Io(message) | ApiError::Cache(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl ApiError
+{
+    // no other request will succeed with the same token
+    pub fn is_unauthorized(&self) -> bool
+    {
+        match self
+        {
+            ApiError::Unauthorized(_, _) => true,
+            _ => false,
+        }
+    }
+
+    fn is_transient(&self) -> bool
+    {
+        match self
+        {
+            ApiError::Server(_, _) | ApiError::Timeout(_) => true,
+            ApiError::Request(_, e) => e.is_connect(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HttpOptions
+{
+    pub timeout: Duration,
+    // attempts after the first one
+    pub retries: u32,
+    // wait before the first retry, doubled for every following one
+    pub backoff: Duration,
+}
+
+impl Default for HttpOptions
+{
+    fn default() -> Self
+    {
+        HttpOptions { timeout: Duration::from_secs(30), retries: 3, backoff: Duration::from_secs(1) }
+    }
+}
+
+fn get_once(client: &reqwest::blocking::Client, url_path: &str, auth_str: &str) -> Result<String, ApiError>
+{
+    let resp = client.get(url_path)
+    .header("accept", "*/*")
+    .header("Authorization", auth_str)
+    .send()
+    .map_err(|e| if e.is_timeout() { ApiError::Timeout(url_path.to_string()) } else { ApiError::Request(url_path.to_string(), e) })?;
+    let status = resp.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN
+    {
+        return Err(ApiError::Unauthorized(url_path.to_string(), status));
+    }
+    if status == reqwest::StatusCode::NOT_FOUND
+    {
+        return Err(ApiError::NotFound(url_path.to_string()));
+    }
+    if status.is_server_error()
+    {
+        return Err(ApiError::Server(url_path.to_string(), status));
+    }
+    if status != reqwest::StatusCode::OK
+    {
+        return Err(ApiError::Status(url_path.to_string(), status));
+    }
+    resp.text().map_err(|e| if e.is_timeout() { ApiError::Timeout(url_path.to_string()) } else { ApiError::Request(url_path.to_string(), e) })
+}
+
+// Retries server errors, timeouts and failed connections with exponential backoff
+pub fn read_json_from_url(client: &reqwest::blocking::Client, url_path: &str, auth_str: &str, options: &HttpOptions) -> Result<String, ApiError>
+{
+    let mut wait = options.backoff;
+    let mut attempt = 0;
+    loop
+    {
+        match get_once(client, url_path, auth_str)
+        {
+            Ok(body) => return Ok(body),
+            Err(e) if e.is_transient() && attempt < options.retries =>
+            {
+                attempt += 1;
+                println!("{}, retry {} of {} in {:?}", e, attempt, options.retries, wait);
+                thread::sleep(wait);
+                wait = wait * 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+pub struct HttpSource
+{
+    api_url: String,
+    cache: Option<ScheduleCache>,
+    client: reqwest::blocking::Client,
+    options: HttpOptions,
+    auth_str: String,
+}
+
+impl HttpSource
+{
+    pub fn new(api_url: &str, cache: Option<ScheduleCache>, options: HttpOptions, auth_str: &str) -> Result<Self, reqwest::Error>
+    {
+        let client = reqwest::blocking::Client::builder().timeout(options.timeout).build()?;
+        Ok(HttpSource { api_url: api_url.trim_end_matches('/').to_string(), cache: cache, client: client, options: options, auth_str: auth_str.to_string() })
+    }
+
+    fn fetch(&self, endpoint: &str, endpoint_path: &str, key: &[&str]) -> Result<String, ApiError>
+    {
+        let mut url_path = format!("{}/{}", self.api_url, endpoint_path);
+        for part in key.iter()
+        {
+            url_path.push_str("/");
+            url_path.push_str(part);
+        }
+        let fetch_url = |url: &str|
+        {
+            println!("reading from url {}", url);
+            read_json_from_url(&self.client, url, &self.auth_str, &self.options)
+        };
+        match self.cache.as_ref()
+        {
+            Some(cache) => cache.get(endpoint, key, &url_path, fetch_url),
+            None => fetch_url(&url_path),
+        }
+    }
+}
+
+impl ScheduleSource for HttpSource
+{
+    fn get_activities_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>
+    {
+        self.fetch(STR_ACTIVITY, STR_PATH_ACTIVITY, &[run, beamline])
+    }
+
+    fn get_beamtime_requests_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>
+    {
+        self.fetch(STR_BEAMTIME_REQUESTS, STR_PATH_BEAMTIME, &[run, beamline])
+    }
+
+    fn get_runs_json(&self) -> Result<String, ApiError>
+    {
+        self.fetch(STR_RUNS, STR_PATH_RUNS, &[])
+    }
+}
+
+fn read_file(file_path: &PathBuf) -> Result<String, ApiError>
+{
+    println!("reading from file {}", file_path.display());
+    let contents = fs::read_to_string(file_path).map_err(|e| ApiError::Io(format!("Error reading {}: {:?}", file_path.display(), e)))?;
+    if contents.is_empty()
+    {
+        return Err(ApiError::Io(format!("Error: file {} is empty", file_path.display())));
+    }
+    Ok(schedule_cache::response_body(contents))
+}
+
+// One saved response returned for every request, what --filename used to do
+pub struct FileSource
+{
+    file_path: PathBuf,
+}
+
+impl FileSource
+{
+    pub fn new(file_path: &str) -> Self
+    {
+        FileSource { file_path: PathBuf::from(file_path) }
+    }
+}
+
+impl ScheduleSource for FileSource
+{
+    fn get_activities_json(&self, _run: &str, _beamline: &str) -> Result<String, ApiError>
+    {
+        read_file(&self.file_path)
+    }
+
+    fn get_beamtime_requests_json(&self, _run: &str, _beamline: &str) -> Result<String, ApiError>
+    {
+        read_file(&self.file_path)
+    }
+
+    fn get_runs_json(&self) -> Result<String, ApiError>
+    {
+        read_file(&self.file_path)
+    }
+}
+
+// Saved responses in the cache layout: <dir>/activity/<run>_<beamline>.json,
+// <dir>/beamtime_requests/<run>_<beamline>.json and <dir>/runs/all.json.
+// A cache directory can be used as is.
+pub struct DirectorySource
+{
+    dir: PathBuf,
+}
+
+impl DirectorySource
+{
+    pub fn new(dir: &str) -> Self
+    {
+        DirectorySource { dir: PathBuf::from(dir) }
+    }
+}
+
+impl ScheduleSource for DirectorySource
+{
+    fn get_activities_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>
+    {
+        read_file(&self.dir.join(schedule_cache::entry_file_name(STR_ACTIVITY, &[run, beamline])))
+    }
+
+    fn get_beamtime_requests_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>
+    {
+        read_file(&self.dir.join(schedule_cache::entry_file_name(STR_BEAMTIME_REQUESTS, &[run, beamline])))
+    }
+
+    fn get_runs_json(&self) -> Result<String, ApiError>
+    {
+        read_file(&self.dir.join(schedule_cache::entry_file_name(STR_RUNS, &[])))
+    }
+}
+
+// Responses held in memory, keyed by (run, beamline)
+#[derive(Default)]
+pub struct FixtureSource
+{
+    pub activities: HashMap<(String, String), String>,
+    pub beamtime_requests: HashMap<(String, String), String>,
+    pub runs: Option<String>,
+}
+
+fn get_fixture(fixtures: &HashMap<(String, String), String>, endpoint: &str, run: &str, beamline: &str) -> Result<String, ApiError>
+{
+    match fixtures.get(&(run.to_string(), beamline.to_string()))
+    {
+        Some(json) => Ok(json.clone()),
+        None => Err(ApiError::Io(format!("Error: no {} fixture for run {} beamline {}", endpoint, run, beamline))),
+    }
+}
+
+impl FixtureSource
+{
+    pub fn add_activities(&mut self, run: &str, beamline: &str, json: &str)
+    {
+        self.activities.insert((run.to_string(), beamline.to_string()), json.to_string());
+    }
+
+    pub fn add_beamtime_requests(&mut self, run: &str, beamline: &str, json: &str)
+    {
+        self.beamtime_requests.insert((run.to_string(), beamline.to_string()), json.to_string());
+    }
+}
+
+impl ScheduleSource for FixtureSource
+{
+    fn get_activities_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>
+    {
+        get_fixture(&self.activities, STR_ACTIVITY, run, beamline)
+    }
+
+    fn get_beamtime_requests_json(&self, run: &str, beamline: &str) -> Result<String, ApiError>
+    {
+        get_fixture(&self.beamtime_requests, STR_BEAMTIME_REQUESTS, run, beamline)
+    }
+
+    fn get_runs_json(&self) -> Result<String, ApiError>
+    {
+        self.runs.clone().ok_or(ApiError::Io(format!("Error: no {} fixture", STR_RUNS)))
+    }
+}
@@ -1,8 +1,9 @@
 //use tokio_postgres::{NoTls, Error};
-use std::env;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
 use serde::Deserialize;
-use postgres::{Client, NoTls, Error};
+use postgres::Client;
+
+// GET /sched-api/run/getAllRuns
 
 #[derive(Deserialize)]
 struct SyncotronRun
@@ -14,33 +15,112 @@ struct SyncotronRun
     version: i32
 }
 
+pub fn parse_time(time_str: &str) -> Result<DateTime<Utc>, chrono::ParseError>
+{
+    match DateTime::parse_from_rfc3339(time_str)
+    {
+        Ok(time) => Ok(time.with_timezone(&Utc)),
+        Err(e) =>
+        {
+            // times without an offset are in the local time of the facility
+            let naive = NaiveDateTime::parse_from_str(time_str, "%Y-%m-%dT%H:%M:%S%.f").map_err(|_| e)?;
+            Ok(Local.from_local_datetime(&naive).earliest().map(|t| t.with_timezone(&Utc)).unwrap_or_else(|| naive.and_utc()))
+        }
+    }
+}
+
 impl SyncotronRun
 {
     fn parse_times(&self) -> Result<(DateTime<Utc>, DateTime<Utc>), chrono::ParseError> {
-        let start_time: DateTime<Utc> = self.startTime.parse().expect("Failed to parse datetime");
-        let end_time:  DateTime<Utc> = self.endTime.parse().expect("Failed to parse datetime");
-        
+        let start_time = parse_time(&self.startTime)?;
+        let end_time = parse_time(&self.endTime)?;
+
         Ok((start_time, end_time))
     }
 
 }
 
-pub fn fill_syncotron_runs(json_data: &str) -> Result<(), postgres::Error> 
+#[derive(Debug)]
+pub enum SyncRunsError
 {
-    let psql_conn_str = env::var("SVC_PSQL_CONN_STR").unwrap_or(String::from("postgresql://localhost/mydata"));
-    let mut client = Client::connect(&psql_conn_str, NoTls)?; 
+    Json(serde_json::Error),
+    Db(postgres::Error),
+}
 
-    let runs: Vec<SyncotronRun> = serde_json::from_str(json_data).expect("Failed to parse JSON");
+impl From<serde_json::Error> for SyncRunsError
+{
+    fn from(e: serde_json::Error) -> Self
+    {
+        SyncRunsError::Json(e)
+    }
+}
 
-    for run in runs 
+impl From<postgres::Error> for SyncRunsError
+{
+    fn from(e: postgres::Error) -> Self
     {
-        let (start_time, end_time) = run.parse_times().expect("Failed to parse times");
-        match client.execute("INSERT INTO syncotron_runs ( name, start_timestamp, end_timestamp) VALUES ($1, $2, $3 )", &[ &run.runName, &start_time, &end_time ], )
+        SyncRunsError::Db(e)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncRunsReport
+{
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: u32,
+    // (run name, reason)
+    pub skipped: Vec<(String, String)>,
+}
+
+impl SyncRunsReport
+{
+    pub fn print(&self)
+    {
+        for name in self.added.iter()
+        {
+            println!("added run {}", name);
+        }
+        for name in self.changed.iter()
+        {
+            println!("changed run {}", name);
+        }
+        for (name, reason) in self.skipped.iter()
         {
-            Ok(_) => { println!("inserted {}", run.runName) }
-            Err(e) => { println!("{:?}", e); }
+            println!("Error: skipped run {}: {}", name, reason);
         }
+        println!("Runs: {} added, {} changed, {} unchanged, {} skipped", self.added.len(), self.changed.len(), self.unchanged, self.skipped.len());
+    }
+}
+
+// Insert new runs by name and update the start and end times of runs that changed
+pub fn fill_syncotron_runs(client: &mut Client, json_data: &str) -> Result<SyncRunsReport, SyncRunsError>
+{
+    let runs: Vec<SyncotronRun> = serde_json::from_str(json_data)?;
+    let mut report = SyncRunsReport::default();
 
+    let query = "INSERT INTO syncotron_runs (name, start_timestamp, end_timestamp) VALUES ($1, $2, $3) \
+                 ON CONFLICT (name) DO UPDATE SET start_timestamp = EXCLUDED.start_timestamp, end_timestamp = EXCLUDED.end_timestamp \
+                 WHERE syncotron_runs.start_timestamp IS DISTINCT FROM EXCLUDED.start_timestamp OR syncotron_runs.end_timestamp IS DISTINCT FROM EXCLUDED.end_timestamp \
+                 RETURNING (xmax = 0) AS inserted";
+    for run in runs
+    {
+        let (start_time, end_time) = match run.parse_times()
+        {
+            Ok(times) => times,
+            Err(e) =>
+            {
+                report.skipped.push((run.runName.clone(), format!("could not parse times {} {}: {:?}", run.startTime, run.endTime, e)));
+                continue;
+            }
+        };
+        let rows = client.query(query, &[ &run.runName, &start_time, &end_time ])?;
+        match rows.first()
+        {
+            Some(row) if row.get::<_, bool>(0) => report.added.push(run.runName),
+            Some(_) => report.changed.push(run.runName),
+            None => report.unchanged += 1,
+        }
     }
-    Ok(())
+    Ok(report)
 }